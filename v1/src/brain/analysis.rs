@@ -0,0 +1,343 @@
+// Simplifying evolved brains and finding out which parts do the work
+//
+// Evolved brains collect neurons and edges that do nothing. prune_off_path and
+// merge_parallel_edges remove structure that cannot matter; constant_neurons finds neurons
+// that ignore the inputs they were shown; ablation_impact and minimal_circuit measure what
+// each neuron contributes to a task, scored by an accuracy function the caller supplies
+// (e.g. the fraction of test inputs answered correctly). Input and output neurons are never
+// removed, so the task's labels keep working.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{Brain, Edge, NeuronId, NeuronType};
+
+// Neurons whose outputs differ by no more than this count as constant
+pub const CONSTANT_TOLERANCE: f64 = 1e-9;
+
+fn reachable(brain: &Brain, from: &[NeuronId], forward: bool) -> BTreeSet<NeuronId> {
+    let mut seen = from
+        .iter()
+        .filter(|id| brain.neurons.contains_key(id))
+        .copied()
+        .collect::<BTreeSet<NeuronId>>();
+    let mut frontier = seen.iter().copied().collect::<Vec<NeuronId>>();
+    while let Some(id) = frontier.pop() {
+        for e in &brain.edges {
+            let (here, next) = if forward {
+                (e.source, e.target)
+            } else {
+                (e.target, e.source)
+            };
+            if here == id && brain.neurons.contains_key(&next) && seen.insert(next) {
+                frontier.push(next);
+            }
+        }
+    }
+    seen
+}
+
+/**
+ * Remove control neurons not on any path from an input to an output neuron, and edges not on
+ * such a path. Returns the removed neurons. Note a neuron driving an output through its bias
+ * alone is off every path and removed too; constant_neurons finds such neurons first.
+ */
+pub fn prune_off_path(brain: &mut Brain) -> Vec<NeuronId> {
+    let from_inputs = reachable(brain, &brain.input_neuron_ids, true);
+    let to_outputs = reachable(brain, &brain.output_neuron_ids, false);
+    let removed = brain
+        .control_neuron_ids
+        .iter()
+        .filter(|id| !(from_inputs.contains(id) && to_outputs.contains(id)))
+        .copied()
+        .collect::<Vec<NeuronId>>();
+    for id in &removed {
+        brain.remove_neuron(*id);
+    }
+    brain
+        .edges
+        .retain(|e| from_inputs.contains(&e.source) && to_outputs.contains(&e.target));
+    removed
+}
+
+/**
+ * Replace edges with the same source and target by one edge with their summed weight, where
+ * the first of them was. Returns how many edges were merged away.
+ */
+pub fn merge_parallel_edges(brain: &mut Brain) -> usize {
+    let before = brain.edges.len();
+    let mut first: BTreeMap<(NeuronId, NeuronId), usize> = BTreeMap::new();
+    let mut merged: Vec<Edge> = Vec::with_capacity(before);
+    for e in brain.edges.drain(..) {
+        match first.get(&(e.source, e.target)) {
+            Some(i) => merged[*i].weight += e.weight,
+            None => {
+                first.insert((e.source, e.target), merged.len());
+                merged.push(e);
+            }
+        }
+    }
+    brain.edges = merged;
+    before - brain.edges.len()
+}
+
+/**
+ * Neurons whose output after n steps of process_n is the same (within CONSTANT_TOLERANCE) for
+ * every observed input, with that output. Each observation starts from reset activations.
+ */
+pub fn constant_neurons(
+    brain: &Brain,
+    observations: &[Vec<(&str, f64)>],
+    n: usize,
+) -> Vec<(NeuronId, f64)> {
+    let runs = observations
+        .iter()
+        .map(|inputs| {
+            let mut b = brain.clone();
+            b.reset_activations();
+            b.process_n(inputs, n)
+        })
+        .collect::<Vec<BTreeMap<NeuronId, f64>>>();
+    let Some((first, rest)) = runs.split_first() else {
+        return Vec::new();
+    };
+    first
+        .iter()
+        .filter(|(id, v)| {
+            rest.iter()
+                .all(|r| (r.get(id).unwrap_or(&0.0) - *v).abs() <= CONSTANT_TOLERANCE)
+        })
+        .map(|(id, v)| (*id, *v))
+        .collect()
+}
+
+fn score(brain: &Brain, accuracy: &dyn Fn(&mut Brain) -> f64) -> f64 {
+    let mut b = brain.clone();
+    b.reset_activations();
+    accuracy(&mut b)
+}
+
+/**
+ * For each control neuron, the drop in accuracy when it is removed with its edges (negative
+ * if the brain does better without it). Largest impact first.
+ */
+pub fn ablation_impact(
+    brain: &Brain,
+    accuracy: &dyn Fn(&mut Brain) -> f64,
+) -> Vec<(NeuronId, f64)> {
+    let baseline = score(brain, accuracy);
+    let mut impacts = brain
+        .control_neuron_ids
+        .iter()
+        .map(|id| {
+            let mut ablated = brain.clone();
+            ablated.remove_neuron(*id);
+            (*id, baseline - score(&ablated, accuracy))
+        })
+        .collect::<Vec<(NeuronId, f64)>>();
+    impacts.sort_by(|a, b| b.1.total_cmp(&a.1));
+    impacts
+}
+
+/**
+ * The smallest circuit found that keeps accuracy within tolerance of the brain's: after
+ * pruning and merging, control neurons are removed greedily, least impact first, then edges
+ * in order, each removal kept only if accuracy stays within tolerance.
+ */
+pub fn minimal_circuit(
+    brain: &Brain,
+    accuracy: &dyn Fn(&mut Brain) -> f64,
+    tolerance: f64,
+) -> Brain {
+    let target = score(brain, accuracy) - tolerance;
+    let mut circuit = brain.clone();
+    prune_off_path(&mut circuit);
+    merge_parallel_edges(&mut circuit);
+    if score(&circuit, accuracy) < target {
+        // Pruning changed behaviour (e.g. a bias-driven neuron); start from the whole brain
+        circuit = brain.clone();
+    }
+
+    let mut order = ablation_impact(&circuit, accuracy);
+    order.reverse();
+    for (id, _) in order {
+        let mut smaller = circuit.clone();
+        smaller.remove_neuron(id);
+        if score(&smaller, accuracy) >= target {
+            circuit = smaller;
+        }
+    }
+    let mut i = 0;
+    while i < circuit.edges.len() {
+        let mut smaller = circuit.clone();
+        smaller.edges.remove(i);
+        if score(&smaller, accuracy) >= target {
+            circuit = smaller;
+        } else {
+            i += 1;
+        }
+    }
+    circuit
+}
+
+#[derive(Debug, Clone)]
+pub struct BrainAnalysis {
+    pub accuracy: f64,
+    // Control neurons off every input to output path
+    pub off_path: Vec<NeuronId>,
+    pub parallel_edges: usize,
+    pub constant: Vec<(NeuronId, f64)>,
+    pub ablation: Vec<(NeuronId, f64)>,
+    pub circuit: Brain,
+    pub circuit_accuracy: f64,
+}
+
+impl BrainAnalysis {
+    /**
+     * Every analysis of this module for one brain. observations and n are as for
+     * constant_neurons, accuracy and tolerance as for minimal_circuit.
+     */
+    pub fn compute(
+        brain: &Brain,
+        observations: &[Vec<(&str, f64)>],
+        n: usize,
+        accuracy: &dyn Fn(&mut Brain) -> f64,
+        tolerance: f64,
+    ) -> Self {
+        let mut pruned = brain.clone();
+        let off_path = prune_off_path(&mut pruned);
+        let mut merged = brain.clone();
+        let parallel_edges = merge_parallel_edges(&mut merged);
+        let circuit = minimal_circuit(brain, accuracy, tolerance);
+        BrainAnalysis {
+            accuracy: score(brain, accuracy),
+            off_path,
+            parallel_edges,
+            constant: constant_neurons(brain, observations, n),
+            ablation: ablation_impact(brain, accuracy),
+            circuit_accuracy: score(&circuit, accuracy),
+            circuit,
+        }
+    }
+}
+
+impl std::fmt::Display for BrainAnalysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "accuracy {:.4}, off-path neurons {:?}, parallel edges {}",
+            self.accuracy, self.off_path, self.parallel_edges
+        )?;
+        write!(f, "constant neurons [")?;
+        for (i, (id, v)) in self.constant.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={:.3}", id, v)?;
+        }
+        writeln!(f, "]")?;
+        write!(f, "ablation impact [")?;
+        for (i, (id, impact)) in self.ablation.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={:.4}", id, impact)?;
+        }
+        writeln!(f, "]")?;
+        write!(
+            f,
+            "minimal circuit: {} control neurons {:?}, {} edges, accuracy {:.4}",
+            self.circuit.ids(NeuronType::Control).len(),
+            self.circuit.control_neuron_ids,
+            self.circuit.edges.len(),
+            self.circuit_accuracy
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::Neuron;
+
+    /**
+     * Input 0 reaches output 5 through control 1, over two parallel edges. Control 2 is a dead
+     * end, 3 nudges the output by its bias alone and 4 is unconnected.
+     */
+    fn brain() -> Brain {
+        let mut brain = Brain::new();
+        brain.add_neuron(Neuron::new(0, 0.0, 0.0), NeuronType::Input, Some("in"));
+        for (id, bias) in [(1, 0.0), (2, 0.0), (3, 0.25), (4, 0.75)] {
+            brain.add_neuron(Neuron::new(id, bias, 0.0), NeuronType::Control, None);
+        }
+        brain.add_neuron(Neuron::new(5, 0.0, 0.0), NeuronType::Output, Some("out"));
+        for (s, t, w) in [
+            (0, 1, 1.0),
+            (1, 5, 0.5),
+            (0, 2, 1.0),
+            (3, 5, 0.2),
+            (1, 5, 0.5),
+        ] {
+            brain.add_edge(Edge::new(s, t, w));
+        }
+        brain
+    }
+
+    // Fraction of inputs 0 and 1 whose output is on the same side of 0.5
+    fn copies_input(brain: &mut Brain) -> f64 {
+        let correct = [0.0, 1.0]
+            .iter()
+            .filter(|x| {
+                brain.reset_activations();
+                let values = brain.process_n(&[("in", **x)], 2);
+                (brain.labeled_value(&values, "out") > 0.5) == (**x > 0.5)
+            })
+            .count();
+        correct as f64 / 2.0
+    }
+
+    #[test]
+    fn prune_keeps_input_to_output_paths() {
+        let mut b = brain();
+        assert_eq!(prune_off_path(&mut b), vec![2, 3, 4]);
+        assert_eq!(b.control_neuron_ids, vec![1]);
+        assert_eq!(
+            (b.input_neuron_ids.clone(), b.output_neuron_ids.clone()),
+            (vec![0], vec![5])
+        );
+        let edges = b
+            .edges
+            .iter()
+            .map(|e| (e.source, e.target))
+            .collect::<Vec<_>>();
+        assert_eq!(edges, vec![(0, 1), (1, 5), (1, 5)]);
+        assert_eq!(merge_parallel_edges(&mut b), 1);
+        assert_eq!(b.edges, vec![Edge::new(0, 1, 1.0), Edge::new(1, 5, 1.0)]);
+    }
+
+    #[test]
+    fn constant_neurons_ignore_the_input() {
+        let observations = vec![vec![("in", 0.0)], vec![("in", 1.0)]];
+        // After one step only the input's direct targets have seen it
+        assert_eq!(
+            constant_neurons(&brain(), &observations, 1),
+            vec![(0, 0.0), (3, 0.25), (4, 0.75), (5, 0.0)]
+        );
+        assert!(constant_neurons(&brain(), &[], 1).is_empty());
+    }
+
+    #[test]
+    fn ablation_finds_the_neuron_doing_the_work() {
+        let b = brain();
+        assert_eq!(copies_input(&mut b.clone()), 1.0);
+        let impacts = ablation_impact(&b, &copies_input);
+        assert_eq!(impacts[0], (1, 0.5));
+        assert!(impacts[1..].iter().all(|(_, impact)| *impact == 0.0));
+
+        let circuit = minimal_circuit(&b, &copies_input, 0.0);
+        assert_eq!(circuit.control_neuron_ids, vec![1]);
+        assert_eq!(
+            circuit.edges,
+            vec![Edge::new(0, 1, 1.0), Edge::new(1, 5, 1.0)]
+        );
+    }
+}
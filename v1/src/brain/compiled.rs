@@ -0,0 +1,224 @@
+// Brains compiled to flat arrays, for evaluating large populations
+//
+// Brain::step builds maps of signals every step. A CompiledBatch holds any number of brains
+// in one set of contiguous arrays: neuron parameters and state indexed by position, and each
+// neuron's incoming edges in compressed sparse row form (edges[offsets[i]..offsets[i + 1]]
+// lead into neuron i). Compiling allocates; process_n does not. Results match Brain::process_n
+// exactly, including carried activations between calls.
+
+use std::ops::Range;
+
+use super::{Brain, NeuronId};
+
+#[derive(Debug, Clone)]
+pub struct CompiledBatch {
+    bias: Vec<f64>,
+    reset_factor: Vec<f64>,
+    activation: Vec<f64>,
+    values: Vec<f64>,
+    next: Vec<f64>,
+    offsets: Vec<usize>,
+    sources: Vec<usize>,
+    weights: Vec<f64>,
+    // Neurons of each brain
+    ranges: Vec<Range<usize>>,
+    // Neuron ids of each brain, in position order
+    ids: Vec<NeuronId>,
+    // Per brain, the position of each input and output label's neuron
+    input_slots: Vec<Option<usize>>,
+    output_slots: Vec<Option<usize>>,
+    input_count: usize,
+    outputs: Vec<f64>,
+}
+
+impl CompiledBatch {
+    /**
+     * Compile brains, reading inputs into the neurons with input_labels and outputs from those
+     * with output_labels. A label a brain lacks reads as 0 and takes no input. Edges from
+     * neurons that do not exist carry nothing and edges into them are dropped, as in
+     * Brain::step. Carried activations are copied from the brains.
+     */
+    pub fn compile(brains: &[Brain], input_labels: &[&str], output_labels: &[&str]) -> Self {
+        let mut batch = CompiledBatch {
+            bias: Vec::new(),
+            reset_factor: Vec::new(),
+            activation: Vec::new(),
+            values: Vec::new(),
+            next: Vec::new(),
+            offsets: vec![0],
+            sources: Vec::new(),
+            weights: Vec::new(),
+            ranges: Vec::new(),
+            ids: Vec::new(),
+            input_slots: Vec::new(),
+            output_slots: Vec::new(),
+            input_count: input_labels.len(),
+            outputs: vec![0.0; brains.len() * output_labels.len()],
+        };
+        for brain in brains {
+            let start = batch.ids.len();
+            // BTreeMap order, so positions follow ids
+            let position = |id: &NeuronId| brain.neurons.range(..id).count() + start;
+            for (id, neuron) in &brain.neurons {
+                batch.ids.push(*id);
+                batch.bias.push(neuron.bias);
+                batch.reset_factor.push(neuron.reset_factor);
+                batch.activation.push(neuron.activation);
+                for edge in brain.edges.iter().filter(|e| e.target == *id) {
+                    if brain.neurons.contains_key(&edge.source) {
+                        batch.sources.push(position(&edge.source));
+                        batch.weights.push(edge.weight);
+                    }
+                }
+                batch.offsets.push(batch.sources.len());
+            }
+            batch.ranges.push(start..batch.ids.len());
+            let slot = |label: &&str| {
+                brain
+                    .label_id(label)
+                    .filter(|id| brain.neurons.contains_key(id))
+                    .map(|id| position(&id))
+            };
+            batch.input_slots.extend(input_labels.iter().map(slot));
+            batch.output_slots.extend(output_labels.iter().map(slot));
+        }
+        batch.values = vec![0.0; batch.ids.len()];
+        batch.next = vec![0.0; batch.ids.len()];
+        batch
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /**
+     * Brain::process_n for every brain. inputs holds each brain's values for the input labels
+     * in turn (brains x input labels). Returns each brain's output label values in turn.
+     */
+    pub fn process_n(&mut self, inputs: &[f64], n: usize) -> &[f64] {
+        assert_eq!(inputs.len(), self.len() * self.input_count);
+        self.values.fill(0.0);
+        for (slot, v) in self.input_slots.iter().zip(inputs) {
+            if let Some(i) = slot {
+                self.values[*i] = *v;
+            }
+        }
+        for _ in 0..n {
+            self.step();
+        }
+        for (out, slot) in self.outputs.iter_mut().zip(&self.output_slots) {
+            *out = slot.map(|i| self.values[i]).unwrap_or(0.0);
+        }
+        &self.outputs
+    }
+
+    // One Brain::step of every brain, in place
+    fn step(&mut self) {
+        for i in 0..self.values.len() {
+            let edges = self.offsets[i]..self.offsets[i + 1];
+            let signals = self.sources[edges.clone()]
+                .iter()
+                .zip(&self.weights[edges])
+                .map(|(s, w)| self.values[*s] * w)
+                .sum::<f64>();
+            let total_input = signals + self.bias[i] + self.activation[i];
+            let output = if total_input.is_nan() {
+                0.0
+            } else {
+                total_input.clamp(0.0, 1.0)
+            };
+            self.activation[i] = output * self.reset_factor[i];
+            self.next[i] = output;
+        }
+        std::mem::swap(&mut self.values, &mut self.next);
+    }
+
+    // Neuron outputs of brain b after the last process_n, by neuron id
+    pub fn neuron_values(&self, b: usize) -> impl Iterator<Item = (NeuronId, f64)> + '_ {
+        let range = self.ranges[b].clone();
+        self.ids[range.clone()]
+            .iter()
+            .copied()
+            .zip(self.values[range].iter().copied())
+    }
+
+    pub fn reset_activations(&mut self) {
+        self.activation.fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::brain::serialization::Samples;
+
+    fn sample0_brains() -> Vec<Brain> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../v2/py/src/stored_organisms/sample0.json");
+        Samples::load(path).unwrap().into_brains()
+    }
+
+    #[test]
+    fn matches_brain_process_n() {
+        let mut brains = sample0_brains();
+        let mut batch = CompiledBatch::compile(&brains, &["input_bad_food"], &["output_eat"]);
+        for (call, input) in [1.0, 0.0, 0.5].iter().enumerate() {
+            let inputs = vec![*input; brains.len()];
+            let outputs = batch.process_n(&inputs, 4 + call).to_vec();
+            for (b, brain) in brains.iter_mut().enumerate() {
+                let values = brain.process_n(&[("input_bad_food", *input)], 4 + call);
+                assert_eq!(outputs[b], brain.labeled_value(&values, "output_eat"));
+                let compiled = batch.neuron_values(b).collect::<BTreeMap<NeuronId, f64>>();
+                assert_eq!(compiled, values);
+            }
+        }
+    }
+
+    // cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn benchmark_10k_organisms_4_steps() {
+        let samples = sample0_brains();
+        let brains = samples
+            .iter()
+            .cycle()
+            .take(10_000)
+            .cloned()
+            .collect::<Vec<Brain>>();
+        let inputs = vec![1.0; brains.len()];
+        let rounds = 20;
+
+        let mut batch = CompiledBatch::compile(&brains, &["input_bad_food"], &["output_eat"]);
+        let start = Instant::now();
+        for _ in 0..rounds {
+            batch.process_n(&inputs, 4);
+        }
+        let compiled = start.elapsed().as_secs_f64() / rounds as f64;
+
+        let mut brains = brains;
+        let start = Instant::now();
+        for _ in 0..rounds {
+            for brain in &mut brains {
+                brain.process_n(&[("input_bad_food", 1.0)], 4);
+            }
+        }
+        let graph = start.elapsed().as_secs_f64() / rounds as f64;
+
+        println!(
+            "10k organisms x 4 steps: compiled {:.2} ms ({:.0} organisms/s), Brain {:.2} ms ({:.0} organisms/s), speedup {:.1}x",
+            compiled * 1000.0,
+            1e4 / compiled,
+            graph * 1000.0,
+            1e4 / graph,
+            graph / compiled
+        );
+    }
+}
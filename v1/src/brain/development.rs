@@ -0,0 +1,347 @@
+// Developmental encoding: decoding a genome into a Brain
+//
+// The genome is read as genes.rs genes (see DEVELOPMENT_SCHEMA), so brain structure evolves
+// under the same clone_with_mutation and duplicate_segment operators as any other genome.
+// Gene tags:
+//   A  Neuron: [type, bias x4, reset factor x4]. Type A input, G output, otherwise control.
+//   C  Connection: [source x2, target x2, weight x4, unused]. Source and target index the
+//      neurons built so far in the current scope, modulo their number, so every value is
+//      valid. Edges into input neurons or out of output neurons are skipped, as in v2.
+//   T  Module boundary: [A to begin a module definition, anything else to end it, unused..].
+//      Genes inside a definition are not expressed where they stand but wherever the module
+//      is instantiated.
+//   G  Instance: [module x2, copies, from x2, to x2, unused x2]. Builds copies+1 (1 to 4)
+//      instances of a module, each in its own scope. The instance's first neuron receives an
+//      edge of weight 1 from neuron `from` of the enclosing scope, its last neuron sends one
+//      to neuron `to`, and all its neurons then join the enclosing scope. Modules may only
+//      instantiate modules defined before them. Nesting multiplies genes (up to 4x per
+//      level), so development stops once max_genes genes have been expressed, counting every
+//      gene of every instance, or once max_neurons neurons exist.
+// Bytes (x4) are read as read4_bases_to_unsigned_byte; biases and reset factors map to
+// [-1, 1] and weights to [-2, 2].
+
+use std::collections::HashMap;
+
+use crate::evol_prim::Base::*;
+use crate::evol_prim::*;
+use crate::genes::{GeneCopy, GeneSchema};
+
+use super::{Brain, Edge, Neuron, NeuronId, NeuronType};
+
+pub const DEVELOPMENT_SCHEMA: GeneSchema = GeneSchema {
+    start: &[T, A, T, A],
+    stop: &[G, G, G],
+    payload_len: 9,
+    kinds: 4,
+};
+
+const NEURON: usize = 0;
+const CONNECTION: usize = 1;
+const MODULE: usize = 2;
+const INSTANCE: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct DevelopmentParams {
+    // Given in order to the input/output neurons built. Labels left over get a neuron of
+    // their own, so process_n always finds them.
+    pub input_labels: Vec<String>,
+    pub output_labels: Vec<String>,
+    // Neuron genes beyond this many neurons are not expressed
+    pub max_neurons: usize,
+    // Genes expressed in total, counting instance genes and each gene of each instance
+    pub max_genes: usize,
+}
+
+fn byte_at(payload: &[Base], at: usize) -> u8 {
+    read4_bases_to_unsigned_byte(&mut payload[at..at + 4].iter())
+}
+
+// Two bases as a number in [0, 15]
+fn index_at(payload: &[Base], at: usize) -> usize {
+    payload[at] as usize * 4 + payload[at + 1] as usize
+}
+
+fn neuron_type(b: Base) -> NeuronType {
+    match b {
+        A => NeuronType::Input,
+        G => NeuronType::Output,
+        _ => NeuronType::Control,
+    }
+}
+
+struct Development<'a> {
+    params: &'a DevelopmentParams,
+    modules: Vec<Vec<GeneCopy>>,
+    types: HashMap<NeuronId, NeuronType>,
+    brain: Brain,
+    // Genes left to express before development stops
+    budget: usize,
+}
+
+impl<'a> Development<'a> {
+    fn is_full(&self) -> bool {
+        self.brain.neurons.len() >= self.params.max_neurons
+    }
+
+    // Express genes into scope; `modules` is how many module definitions they may use
+    fn expand(&mut self, genes: &[GeneCopy], modules: usize, scope: &mut Vec<NeuronId>) {
+        for gene in genes {
+            if self.budget == 0 {
+                return;
+            }
+            self.budget -= 1;
+            let p = &gene.payload;
+            match gene.kind {
+                NEURON => {
+                    if self.is_full() {
+                        continue;
+                    }
+                    let id = self.brain.neurons.len();
+                    let bias = byte_to_feature_space(byte_at(p, 1)) as f64;
+                    let reset_factor = byte_to_feature_space(byte_at(p, 5)) as f64;
+                    let t = neuron_type(p[0]);
+                    self.brain
+                        .add_neuron(Neuron::new(id, bias, reset_factor), t, None);
+                    self.types.insert(id, t);
+                    scope.push(id);
+                }
+                CONNECTION => {
+                    if scope.is_empty() {
+                        continue;
+                    }
+                    let source = scope[index_at(p, 0) % scope.len()];
+                    let target = scope[index_at(p, 2) % scope.len()];
+                    let weight = byte_to_feature_space(byte_at(p, 4)) as f64 * 2.0;
+                    self.connect(source, target, weight);
+                }
+                INSTANCE => {
+                    if modules == 0 || self.is_full() {
+                        continue;
+                    }
+                    let module = index_at(p, 0) % modules;
+                    let copies = p[2] as usize + 1;
+                    let (from, to) = (index_at(p, 3), index_at(p, 5));
+                    for _ in 0..copies {
+                        if self.budget == 0 || self.is_full() {
+                            break;
+                        }
+                        let genes = self.modules[module].clone();
+                        let mut local = Vec::new();
+                        self.expand(&genes, module, &mut local);
+                        if let (Some(first), Some(last)) = (local.first(), local.last()) {
+                            if !scope.is_empty() {
+                                self.connect(scope[from % scope.len()], *first, 1.0);
+                                self.connect(*last, scope[to % scope.len()], 1.0);
+                            }
+                        }
+                        scope.extend(local);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn connect(&mut self, source: NeuronId, target: NeuronId, weight: f64) {
+        if self.types[&target] != NeuronType::Input && self.types[&source] != NeuronType::Output {
+            self.brain.add_edge(Edge::new(source, target, weight));
+        }
+    }
+
+    fn label(&mut self, neuron_type: NeuronType, labels: &[String]) {
+        for (k, label) in labels.iter().enumerate() {
+            let id = match self.brain.ids(neuron_type).get(k) {
+                Some(id) => *id,
+                None => {
+                    let id = self.brain.neurons.len();
+                    self.brain
+                        .add_neuron(Neuron::new(id, 0.0, 0.0), neuron_type, None);
+                    id
+                }
+            };
+            self.brain.labeled_neurons.insert(label.clone(), id);
+        }
+    }
+}
+
+/**
+ * Build the brain a genome encodes. Functional genes are read in order; module definitions
+ * are collected first so instances anywhere at the top level can use any module.
+ */
+pub fn develop(seq: &[Base], params: &DevelopmentParams) -> Brain {
+    let mut top_level = Vec::new();
+    let mut modules = Vec::new();
+    let mut defining: Option<Vec<GeneCopy>> = None;
+    for gene in DEVELOPMENT_SCHEMA.decode(seq) {
+        if !gene.functional {
+            continue;
+        }
+        if gene.kind == MODULE {
+            if let Some(module) = defining.take() {
+                modules.push(module);
+            }
+            if gene.payload[0] == A {
+                defining = Some(Vec::new());
+            }
+        } else if let Some(module) = &mut defining {
+            module.push(gene);
+        } else {
+            top_level.push(gene);
+        }
+    }
+    if let Some(module) = defining {
+        modules.push(module);
+    }
+
+    let mut development = Development {
+        params,
+        modules,
+        types: HashMap::new(),
+        brain: Brain::new(),
+        budget: params.max_genes,
+    };
+    let module_count = development.modules.len();
+    development.expand(&top_level, module_count, &mut Vec::new());
+    development.label(NeuronType::Input, &params.input_labels);
+    development.label(NeuronType::Output, &params.output_labels);
+    development.brain
+}
+
+// Gene constructors, e.g. for hand-written ancestors
+
+pub fn neuron_gene(neuron_type: NeuronType, bias: f32, reset_factor: f32) -> BaseSeq {
+    let t = match neuron_type {
+        NeuronType::Input => A,
+        NeuronType::Control => C,
+        NeuronType::Output => G,
+    };
+    let mut payload = vec![t];
+    payload.extend(unsigned_byte_to_4_bases(feature_space_to_byte(bias)));
+    payload.extend(unsigned_byte_to_4_bases(feature_space_to_byte(
+        reset_factor,
+    )));
+    DEVELOPMENT_SCHEMA.encode(NEURON, &payload)
+}
+
+fn index_bases(index: usize) -> [Base; 2] {
+    [BASES[(index / 4) % 4], BASES[index % 4]]
+}
+
+pub fn connection_gene(source: usize, target: usize, weight: f32) -> BaseSeq {
+    let mut payload = Vec::new();
+    payload.extend(index_bases(source));
+    payload.extend(index_bases(target));
+    payload.extend(unsigned_byte_to_4_bases(feature_space_to_byte(
+        weight / 2.0,
+    )));
+    payload.push(A);
+    DEVELOPMENT_SCHEMA.encode(CONNECTION, &payload)
+}
+
+pub fn module_begin_gene() -> BaseSeq {
+    DEVELOPMENT_SCHEMA.encode(MODULE, &[A; 9])
+}
+
+pub fn module_end_gene() -> BaseSeq {
+    DEVELOPMENT_SCHEMA.encode(MODULE, &[C; 9])
+}
+
+// `copies` in [1, 4]
+pub fn instance_gene(module: usize, copies: usize, from: usize, to: usize) -> BaseSeq {
+    let mut payload = Vec::new();
+    payload.extend(index_bases(module));
+    payload.push(BASES[copies.clamp(1, 4) - 1]);
+    payload.extend(index_bases(from));
+    payload.extend(index_bases(to));
+    payload.extend([A, A]);
+    DEVELOPMENT_SCHEMA.encode(INSTANCE, &payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(max_neurons: usize, max_genes: usize) -> DevelopmentParams {
+        DevelopmentParams {
+            input_labels: vec!["in".to_string()],
+            output_labels: vec!["out".to_string()],
+            max_neurons,
+            max_genes,
+        }
+    }
+
+    fn has_edge(brain: &Brain, source: NeuronId, target: NeuronId) -> bool {
+        brain
+            .edges
+            .iter()
+            .any(|e| e.source == source && e.target == target)
+    }
+
+    // Module 0 is one control neuron; module 1 is two instances of module 0 chained by from/to
+    fn nested_genome() -> BaseSeq {
+        [
+            module_begin_gene(),
+            neuron_gene(NeuronType::Control, 0.0, 0.0),
+            module_end_gene(),
+            module_begin_gene(),
+            neuron_gene(NeuronType::Control, 0.0, 0.0),
+            instance_gene(0, 2, 0, 0),
+            module_end_gene(),
+            neuron_gene(NeuronType::Input, 0.0, 0.0),
+            neuron_gene(NeuronType::Output, 0.0, 0.0),
+            instance_gene(1, 1, 0, 1),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn nested_modules_are_wired_from_and_to_the_enclosing_scope() {
+        let brain = develop(&nested_genome(), &params(100, 1000));
+        // Input 0, output 1, then module 1: its neuron 2 and module 0 instances 3 and 4
+        assert_eq!(brain.neurons.len(), 5);
+        assert_eq!(brain.input_neuron_ids, vec![0]);
+        assert_eq!(brain.output_neuron_ids, vec![1]);
+        assert_eq!(brain.control_neuron_ids, vec![2, 3, 4]);
+        // Inside module 1, each instance of module 0 hangs off neuron 2 (from = to = 0)
+        for instance in [3, 4] {
+            assert!(has_edge(&brain, 2, instance));
+            assert!(has_edge(&brain, instance, 2));
+        }
+        // At the top level, module 1 takes input 0 into its first neuron and sends its last
+        // neuron to output 1
+        assert!(has_edge(&brain, 0, 2));
+        assert!(has_edge(&brain, 4, 1));
+        assert_eq!(brain.edges.len(), 6);
+        assert_eq!(brain.label_id("in"), Some(0));
+        assert_eq!(brain.label_id("out"), Some(1));
+    }
+
+    #[test]
+    fn neurons_stop_at_max_neurons() {
+        let brain = develop(&nested_genome(), &params(3, 1000));
+        assert_eq!(brain.neurons.len(), 3);
+        assert!(brain.edges.iter().all(|e| e.source < 3 && e.target < 3));
+    }
+
+    #[test]
+    fn deep_nesting_stops_at_max_genes() {
+        // Each module instantiates the one before it 4 times, 12 levels deep: 4^12 neurons
+        // if fully expanded
+        let mut genome = [
+            module_begin_gene(),
+            neuron_gene(NeuronType::Control, 0.0, 0.0),
+        ]
+        .concat();
+        for module in 0..12 {
+            genome.extend(module_begin_gene());
+            genome.extend(instance_gene(module, 4, 0, 0));
+        }
+        genome.extend(module_end_gene());
+        genome.extend(instance_gene(12, 4, 0, 0));
+
+        let brain = develop(&genome, &params(usize::MAX, 500));
+        assert!(brain.neurons.len() <= 500 + 2);
+        let brain = develop(&genome, &params(50, usize::MAX));
+        assert_eq!(brain.neurons.len(), 50 + 2);
+    }
+}
@@ -0,0 +1,326 @@
+// Recurrent brain of neurons and weighted edges, a port of v2's Python Brain
+//
+// Every step each neuron receives the previous step's outputs through its incoming edges,
+// so a signal needs one step per edge to travel. Input values are only present at the first
+// step of process_n. Neurons keep part of their last output (reset_factor) between steps
+// and between calls, until reset_activations.
+
+pub mod analysis;
+pub mod compiled;
+pub mod development;
+pub mod neat;
+pub mod neuron;
+pub mod plasticity;
+pub mod serialization;
+
+use std::collections::{BTreeMap, HashMap};
+
+use rand::prelude::ThreadRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+pub use neuron::{Edge, Neuron};
+
+pub type NeuronId = usize;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NeuronType {
+    Input,
+    Control,
+    Output,
+}
+
+// Serializes to v2's Brain.to_json format, see serialization
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Brain {
+    #[serde(with = "serialization::neuron_list")]
+    pub neurons: BTreeMap<NeuronId, Neuron>,
+    pub edges: Vec<Edge>,
+    pub input_neuron_ids: Vec<NeuronId>,
+    pub control_neuron_ids: Vec<NeuronId>,
+    pub output_neuron_ids: Vec<NeuronId>,
+    // Optional in older stored files
+    #[serde(default)]
+    pub labeled_neurons: HashMap<String, NeuronId>,
+}
+
+impl Brain {
+    pub fn new() -> Self {
+        Brain::default()
+    }
+
+    pub fn add_neuron(&mut self, neuron: Neuron, neuron_type: NeuronType, label: Option<&str>) {
+        let id = neuron.id;
+        self.neurons.insert(id, neuron);
+        self.ids_mut(neuron_type).push(id);
+        if let Some(label) = label {
+            self.labeled_neurons.insert(label.to_string(), id);
+        }
+    }
+
+    pub fn add_edge(&mut self, edge: Edge) {
+        self.edges.push(edge);
+    }
+
+    pub fn ids(&self, neuron_type: NeuronType) -> &Vec<NeuronId> {
+        match neuron_type {
+            NeuronType::Input => &self.input_neuron_ids,
+            NeuronType::Control => &self.control_neuron_ids,
+            NeuronType::Output => &self.output_neuron_ids,
+        }
+    }
+
+    fn ids_mut(&mut self, neuron_type: NeuronType) -> &mut Vec<NeuronId> {
+        match neuron_type {
+            NeuronType::Input => &mut self.input_neuron_ids,
+            NeuronType::Control => &mut self.control_neuron_ids,
+            NeuronType::Output => &mut self.output_neuron_ids,
+        }
+    }
+
+    pub fn label_id(&self, label: &str) -> Option<NeuronId> {
+        self.labeled_neurons.get(label).copied()
+    }
+
+    /**
+     * Set the labeled input neurons to the given values, then run n steps. Returns every
+     * neuron's output after the last step. Panics on an unknown label, as v2 does.
+     */
+    pub fn process_n(
+        &mut self,
+        input_neuron_values: &[(&str, f64)],
+        n: usize,
+    ) -> BTreeMap<NeuronId, f64> {
+        let mut neuron_values = self.labeled_inputs(input_neuron_values);
+        for _ in 0..n {
+            neuron_values = self.step(&neuron_values);
+        }
+        neuron_values
+    }
+
+    fn labeled_inputs(&self, input_neuron_values: &[(&str, f64)]) -> BTreeMap<NeuronId, f64> {
+        input_neuron_values
+            .iter()
+            .map(|(label, v)| {
+                let id = self
+                    .label_id(label)
+                    .unwrap_or_else(|| panic!("Unknown neuron label {}", label));
+                (id, *v)
+            })
+            .collect()
+    }
+
+    pub fn step(&mut self, neuron_values: &BTreeMap<NeuronId, f64>) -> BTreeMap<NeuronId, f64> {
+        let mut signals = self
+            .neurons
+            .keys()
+            .map(|id| (*id, Vec::new()))
+            .collect::<BTreeMap<NeuronId, Vec<f64>>>();
+        for edge in &self.edges {
+            let source_output = *neuron_values.get(&edge.source).unwrap_or(&0.0);
+            if let Some(s) = signals.get_mut(&edge.target) {
+                s.push(edge.transmit(source_output));
+            }
+        }
+        self.neurons
+            .iter_mut()
+            .map(|(id, neuron)| (*id, neuron.activate(&signals[id])))
+            .collect()
+    }
+
+    // Output of the neuron with the given label in process_n's result, 0 if absent
+    pub fn labeled_value(&self, values: &BTreeMap<NeuronId, f64>, label: &str) -> f64 {
+        self.label_id(label)
+            .and_then(|id| values.get(&id).copied())
+            .unwrap_or(0.0)
+    }
+
+    // Forget carried activations, e.g. for a child's copy of its parent's brain
+    pub fn reset_activations(&mut self) {
+        for neuron in self.neurons.values_mut() {
+            neuron.activation = 0.0;
+        }
+    }
+
+    // From an input or control neuron to a control or output neuron, weight ~ N(0,1)*4 - 2
+    pub fn add_random_edge(&mut self, rng: &mut ThreadRng) {
+        let sources = [&self.input_neuron_ids[..], &self.control_neuron_ids[..]].concat();
+        let targets = [&self.control_neuron_ids[..], &self.output_neuron_ids[..]].concat();
+        if let (Some(src), Some(dst)) = (sources.choose(rng), targets.choose(rng)) {
+            let weight = rng.sample::<f64, _>(StandardNormal) * 4.0 - 2.0;
+            self.add_edge(Edge::new(*src, *dst, weight));
+        }
+    }
+
+    pub fn remove_random_edge(&mut self, rng: &mut ThreadRng) {
+        if !self.edges.is_empty() {
+            let i = rng.gen_range(0..self.edges.len());
+            self.edges.remove(i);
+        }
+    }
+
+    /**
+     * Input and output neurons start with no bias or carried activation; control neurons get
+     * bias ~ N(0,1)*2 - 1 and reset factor ~ N(0,1). Returns the new neuron's id.
+     */
+    pub fn add_default_neuron(
+        &mut self,
+        neuron_type: NeuronType,
+        label: Option<&str>,
+        rng: &mut ThreadRng,
+    ) -> NeuronId {
+        let (bias, reset_factor) = match neuron_type {
+            NeuronType::Input | NeuronType::Output => (0.0, 0.0),
+            NeuronType::Control => (
+                rng.sample::<f64, _>(StandardNormal) * 2.0 - 1.0,
+                rng.sample::<f64, _>(StandardNormal),
+            ),
+        };
+        let id = self
+            .neurons
+            .keys()
+            .next_back()
+            .map(|id| id + 1)
+            .unwrap_or(0);
+        self.add_neuron(Neuron::new(id, bias, reset_factor), neuron_type, label);
+        id
+    }
+
+    // Also drops any label of the removed neuron
+    pub fn remove_random_neuron(
+        &mut self,
+        neuron_type: NeuronType,
+        autoprune: bool,
+        rng: &mut ThreadRng,
+    ) {
+        let ids = self.ids_mut(neuron_type);
+        if ids.is_empty() {
+            return;
+        }
+        let id = ids.remove(rng.gen_range(0..ids.len()));
+        self.neurons.remove(&id);
+        self.labeled_neurons.retain(|_, l| *l != id);
+        if autoprune {
+            self.prune_disconnected_edges();
+        }
+    }
+
+    // Remove a neuron with its edges and any label of it
+    pub fn remove_neuron(&mut self, id: NeuronId) {
+        self.neurons.remove(&id);
+        for t in [NeuronType::Input, NeuronType::Control, NeuronType::Output] {
+            self.ids_mut(t).retain(|i| *i != id);
+        }
+        self.labeled_neurons.retain(|_, l| *l != id);
+        self.edges.retain(|e| e.source != id && e.target != id);
+    }
+
+    // Drop edges whose source or target neuron no longer exists
+    pub fn prune_disconnected_edges(&mut self) {
+        let neurons = &self.neurons;
+        self.edges
+            .retain(|e| neurons.contains_key(&e.source) && neurons.contains_key(&e.target));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // in (0) -> control (1) -> out (2), weights 1
+    fn chain(reset_factor: f64) -> Brain {
+        let mut brain = Brain::new();
+        brain.add_neuron(Neuron::new(0, 0.0, 0.0), NeuronType::Input, Some("in"));
+        brain.add_neuron(Neuron::new(1, 0.0, reset_factor), NeuronType::Control, None);
+        brain.add_neuron(Neuron::new(2, 0.0, 0.0), NeuronType::Output, Some("out"));
+        brain.add_edge(Edge::new(0, 1, 1.0));
+        brain.add_edge(Edge::new(1, 2, 1.0));
+        brain
+    }
+
+    fn values(v: &[(NeuronId, f64)]) -> BTreeMap<NeuronId, f64> {
+        v.iter().copied().collect()
+    }
+
+    #[test]
+    fn signals_take_one_step_per_edge() {
+        let mut brain = chain(0.0);
+        assert_eq!(
+            brain.process_n(&[("in", 0.5)], 1),
+            values(&[(0, 0.0), (1, 0.5), (2, 0.0)])
+        );
+        // The input is gone after the first step, so only its echo reaches the output
+        let mut brain = chain(0.0);
+        assert_eq!(
+            brain.process_n(&[("in", 0.5)], 2),
+            values(&[(0, 0.0), (1, 0.0), (2, 0.5)])
+        );
+        assert_eq!(brain.process_n(&[("in", 0.5)], 3)[&2], 0.0);
+    }
+
+    #[test]
+    fn reset_factor_carries_activation_across_calls() {
+        let mut brain = chain(0.5);
+        assert_eq!(brain.process_n(&[("in", 0.8)], 1)[&1], 0.8);
+        assert_eq!(brain.process_n(&[("in", 0.0)], 1)[&1], 0.4);
+        assert_eq!(brain.process_n(&[("in", 0.0)], 1)[&1], 0.2);
+        brain.reset_activations();
+        assert_eq!(brain.process_n(&[("in", 0.0)], 1)[&1], 0.0);
+    }
+
+    #[test]
+    fn outputs_are_clamped_to_0_1() {
+        let mut brain = chain(0.0);
+        brain.neurons.get_mut(&1).unwrap().bias = 0.25;
+        assert_eq!(brain.process_n(&[("in", 0.5)], 1)[&1], 0.75);
+        assert_eq!(brain.process_n(&[("in", 3.0)], 1)[&1], 1.0);
+        assert_eq!(brain.process_n(&[("in", -3.0)], 1)[&1], 0.0);
+        // v2 maps NaN to 0
+        assert_eq!(brain.process_n(&[("in", f64::NAN)], 1)[&1], 0.0);
+        assert_eq!(brain.labeled_value(&values(&[(2, 0.5)]), "out"), 0.5);
+        assert_eq!(brain.labeled_value(&values(&[(2, 0.5)]), "missing"), 0.0);
+    }
+
+    #[test]
+    fn default_neurons_take_the_next_free_id() {
+        let mut rng = rand::thread_rng();
+        let mut brain = Brain::new();
+        assert_eq!(
+            brain.add_default_neuron(NeuronType::Input, Some("in"), &mut rng),
+            0
+        );
+        brain.add_neuron(Neuron::new(7, 0.0, 0.0), NeuronType::Control, None);
+        assert_eq!(
+            brain.add_default_neuron(NeuronType::Output, None, &mut rng),
+            8
+        );
+        assert_eq!(brain.neurons[&8], Neuron::new(8, 0.0, 0.0));
+        assert_eq!(brain.ids(NeuronType::Output), &vec![8]);
+        assert_eq!(brain.label_id("in"), Some(0));
+    }
+
+    #[test]
+    fn removing_neurons_drops_their_edges_and_labels() {
+        let mut brain = chain(0.0);
+        brain.remove_neuron(2);
+        assert!(brain.output_neuron_ids.is_empty());
+        assert_eq!(brain.label_id("out"), None);
+        assert_eq!(brain.edges, vec![Edge::new(0, 1, 1.0)]);
+
+        // The only control neuron is the one removed
+        let mut rng = rand::thread_rng();
+        let mut brain = chain(0.0);
+        brain.remove_random_neuron(NeuronType::Control, false, &mut rng);
+        assert!(!brain.neurons.contains_key(&1));
+        assert_eq!(brain.edges.len(), 2);
+        brain.prune_disconnected_edges();
+        assert!(brain.edges.is_empty());
+
+        let mut brain = chain(0.0);
+        brain.remove_random_neuron(NeuronType::Output, true, &mut rng);
+        assert_eq!(brain.label_id("out"), None);
+        assert_eq!(brain.edges, vec![Edge::new(0, 1, 1.0)]);
+    }
+}
@@ -0,0 +1,579 @@
+// NEAT-style topology evolution for direct-encoded brains
+//
+// A NeatGenome is a Brain's neurons plus its connections tagged with innovation numbers. The
+// Innovations record is shared by the whole run, so the same structural mutation gets the
+// same innovation number (and a split the same node id) in every organism it happens in.
+// That lets crossover line up genes from two parents and lets distance count the genes
+// they do not share.
+
+use std::collections::{BTreeMap, HashMap};
+
+use rand::prelude::ThreadRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+use super::{Brain, Edge, Neuron, NeuronId, NeuronType};
+
+pub type Innovation = u64;
+
+// Chance a gene disabled in either parent stays disabled in the child
+pub const INHERIT_DISABLED_PROB: f64 = 0.75;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionGene {
+    pub innovation: Innovation,
+    pub source: NeuronId,
+    pub target: NeuronId,
+    pub weight: f64,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeGene {
+    pub neuron: Neuron,
+    pub neuron_type: NeuronType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NeatGenome {
+    pub nodes: BTreeMap<NeuronId, NodeGene>,
+    // Sorted by innovation
+    pub connections: Vec<ConnectionGene>,
+    pub labeled_neurons: HashMap<String, NeuronId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Innovations {
+    next_innovation: Innovation,
+    next_node: NeuronId,
+    connections: HashMap<(NeuronId, NeuronId), Innovation>,
+    // Innovation of the split connection -> node inserted into it
+    splits: HashMap<Innovation, NeuronId>,
+}
+
+impl Innovations {
+    pub fn new() -> Self {
+        Innovations::default()
+    }
+
+    pub fn connection(&mut self, source: NeuronId, target: NeuronId) -> Innovation {
+        let next = &mut self.next_innovation;
+        *self.connections.entry((source, target)).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+
+    pub fn split_node(&mut self, innovation: Innovation) -> NeuronId {
+        let next = &mut self.next_node;
+        *self.splits.entry(innovation).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+
+    // Node ids already in use (e.g. by the initial brains) are never handed out for splits
+    pub fn reserve_node(&mut self, id: NeuronId) {
+        self.next_node = self.next_node.max(id + 1);
+    }
+}
+
+// Coefficients of the compatibility distance (c1 E + c2 D) / N + c3 W
+#[derive(Debug, Copy, Clone)]
+pub struct DistanceCoefficients {
+    pub excess: f64,
+    pub disjoint: f64,
+    pub weight: f64,
+}
+
+impl Default for DistanceCoefficients {
+    // The values of the original NEAT paper
+    fn default() -> Self {
+        DistanceCoefficients {
+            excess: 1.0,
+            disjoint: 1.0,
+            weight: 0.4,
+        }
+    }
+}
+
+impl NeatGenome {
+    /**
+     * Tag each edge with an innovation number. Brains of one population should share neuron
+     * ids for the same structure, e.g. by starting from copies of one brain.
+     */
+    pub fn from_brain(brain: &Brain, innovations: &mut Innovations) -> Self {
+        let mut nodes = BTreeMap::new();
+        for t in [NeuronType::Input, NeuronType::Control, NeuronType::Output] {
+            for id in brain.ids(t) {
+                if let Some(neuron) = brain.neurons.get(id) {
+                    innovations.reserve_node(*id);
+                    nodes.insert(
+                        *id,
+                        NodeGene {
+                            neuron: neuron.clone(),
+                            neuron_type: t,
+                        },
+                    );
+                }
+            }
+        }
+        let mut genome = NeatGenome {
+            nodes,
+            connections: Vec::new(),
+            labeled_neurons: brain.labeled_neurons.clone(),
+        };
+        for e in &brain.edges {
+            genome.add_connection(innovations, e.source, e.target, e.weight);
+        }
+        genome
+    }
+
+    // Enabled connections only; neuron activations start from 0
+    pub fn to_brain(&self) -> Brain {
+        let mut brain = Brain::new();
+        for node in self.nodes.values() {
+            let mut neuron = node.neuron.clone();
+            neuron.activation = 0.0;
+            brain.add_neuron(neuron, node.neuron_type, None);
+        }
+        for c in self.connections.iter().filter(|c| c.enabled) {
+            brain.add_edge(Edge::new(c.source, c.target, c.weight));
+        }
+        brain.labeled_neurons = self.labeled_neurons.clone();
+        brain
+    }
+
+    fn add_connection(
+        &mut self,
+        innovations: &mut Innovations,
+        source: NeuronId,
+        target: NeuronId,
+        weight: f64,
+    ) {
+        let innovation = innovations.connection(source, target);
+        let gene = ConnectionGene {
+            innovation,
+            source,
+            target,
+            weight,
+            enabled: true,
+        };
+        match self
+            .connections
+            .binary_search_by_key(&innovation, |c| c.innovation)
+        {
+            // Already present (e.g. parallel edges in the brain): re-enable it instead
+            Ok(i) => self.connections[i].enabled = true,
+            Err(i) => self.connections.insert(i, gene),
+        }
+    }
+
+    fn ids_of(&self, types: &[NeuronType]) -> Vec<NeuronId> {
+        self.nodes
+            .iter()
+            .filter(|(_, n)| types.contains(&n.neuron_type))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /**
+     * Connect a random input or control neuron to a random control or output neuron not
+     * already connected from it, with weight ~ N(0,1)*4 - 2 as in Brain::add_random_edge.
+     * Returns false if every such pair is already connected.
+     */
+    pub fn mutate_add_connection(
+        &mut self,
+        innovations: &mut Innovations,
+        rng: &mut ThreadRng,
+    ) -> bool {
+        let sources = self.ids_of(&[NeuronType::Input, NeuronType::Control]);
+        let targets = self.ids_of(&[NeuronType::Control, NeuronType::Output]);
+        let free = sources
+            .iter()
+            .flat_map(|s| targets.iter().map(move |t| (*s, *t)))
+            .filter(|(s, t)| {
+                !self
+                    .connections
+                    .iter()
+                    .any(|c| c.source == *s && c.target == *t)
+            })
+            .collect::<Vec<(NeuronId, NeuronId)>>();
+        match free.choose(rng) {
+            Some((source, target)) => {
+                let weight = rng.sample::<f64, _>(StandardNormal) * 4.0 - 2.0;
+                self.add_connection(innovations, *source, *target, weight);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /**
+     * Disable a random enabled connection and route it through a new control neuron: the
+     * incoming connection gets weight 1 and the outgoing one the old weight, so behaviour
+     * changes as little as possible. Returns the new neuron's id.
+     */
+    pub fn mutate_split_connection(
+        &mut self,
+        innovations: &mut Innovations,
+        rng: &mut ThreadRng,
+    ) -> Option<NeuronId> {
+        let enabled = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.enabled)
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        let i = *enabled.choose(rng)?;
+        let split = self.connections[i].clone();
+        // Genomes not built by from_brain may not have reserved their node ids yet
+        for id in self.nodes.keys() {
+            innovations.reserve_node(*id);
+        }
+        let node = innovations.split_node(split.innovation);
+        if self.nodes.contains_key(&node) {
+            // This genome already split the connection once before, or the node id another
+            // genome got for this split is one of this genome's own neurons
+            return None;
+        }
+        self.connections[i].enabled = false;
+        self.nodes.insert(
+            node,
+            NodeGene {
+                neuron: Neuron::new(node, 0.0, 0.0),
+                neuron_type: NeuronType::Control,
+            },
+        );
+        self.add_connection(innovations, split.source, node, 1.0);
+        self.add_connection(innovations, node, split.target, split.weight);
+        Some(node)
+    }
+
+    // Each weight is perturbed by N(0, power) with probability prob
+    pub fn mutate_weights(&mut self, prob: f64, power: f64, rng: &mut ThreadRng) {
+        for c in &mut self.connections {
+            if rng.gen::<f64>() < prob {
+                c.weight += rng.sample::<f64, _>(StandardNormal) * power;
+            }
+        }
+    }
+
+    /**
+     * Matching genes are inherited from either parent at random, disjoint and excess genes
+     * from `fitter` only. Nodes come from `fitter`, plus any of `other`'s that inherited
+     * connections need.
+     */
+    pub fn crossover(fitter: &NeatGenome, other: &NeatGenome, rng: &mut ThreadRng) -> NeatGenome {
+        let other_genes = other
+            .connections
+            .iter()
+            .map(|c| (c.innovation, c))
+            .collect::<HashMap<Innovation, &ConnectionGene>>();
+        let connections = fitter
+            .connections
+            .iter()
+            .map(|c| match other_genes.get(&c.innovation) {
+                Some(o) => {
+                    let mut gene = if rng.gen::<bool>() {
+                        c.clone()
+                    } else {
+                        (*o).clone()
+                    };
+                    let disabled = !c.enabled || !o.enabled;
+                    gene.enabled = !(disabled && rng.gen::<f64>() < INHERIT_DISABLED_PROB);
+                    gene
+                }
+                None => c.clone(),
+            })
+            .collect::<Vec<ConnectionGene>>();
+
+        let mut nodes = fitter.nodes.clone();
+        for c in &connections {
+            for id in [c.source, c.target] {
+                if let (false, Some(n)) = (nodes.contains_key(&id), other.nodes.get(&id)) {
+                    nodes.insert(id, n.clone());
+                }
+            }
+        }
+        NeatGenome {
+            nodes,
+            connections,
+            labeled_neurons: fitter.labeled_neurons.clone(),
+        }
+    }
+
+    /**
+     * Compatibility distance: excess genes lie beyond the other genome's highest innovation,
+     * disjoint genes are the other unmatched ones, and W is the mean weight difference of
+     * matching genes. N is the larger genome's connection count (at least 1).
+     */
+    pub fn distance(&self, other: &NeatGenome, c: &DistanceCoefficients) -> f64 {
+        let max_a = self.connections.last().map(|g| g.innovation);
+        let max_b = other.connections.last().map(|g| g.innovation);
+        let (mut excess, mut disjoint, mut matching, mut weight_diff) = (0, 0, 0, 0.0);
+        let (mut i, mut j) = (0, 0);
+        while i < self.connections.len() || j < other.connections.len() {
+            match (self.connections.get(i), other.connections.get(j)) {
+                (Some(a), Some(b)) if a.innovation == b.innovation => {
+                    matching += 1;
+                    weight_diff += (a.weight - b.weight).abs();
+                    i += 1;
+                    j += 1;
+                }
+                (Some(a), Some(b)) if a.innovation < b.innovation => {
+                    disjoint += 1;
+                    i += 1;
+                }
+                (Some(_), Some(_)) => {
+                    disjoint += 1;
+                    j += 1;
+                }
+                (Some(a), None) => {
+                    if max_b.is_none_or(|m| a.innovation > m) {
+                        excess += 1;
+                    } else {
+                        disjoint += 1;
+                    }
+                    i += 1;
+                }
+                (None, Some(b)) => {
+                    if max_a.is_none_or(|m| b.innovation > m) {
+                        excess += 1;
+                    } else {
+                        disjoint += 1;
+                    }
+                    j += 1;
+                }
+                (None, None) => break,
+            }
+        }
+        let n = self.connections.len().max(other.connections.len()).max(1) as f64;
+        let mean_weight_diff = if matching == 0 {
+            0.0
+        } else {
+            weight_diff / matching as f64
+        };
+        (c.excess * excess as f64 + c.disjoint * disjoint as f64) / n + c.weight * mean_weight_diff
+    }
+}
+
+/**
+ * NEAT speciation: each genome joins the first species whose representative is within
+ * `threshold`, otherwise founds a new one. Representatives persist between calls (pass the
+ * same vector each generation). Returns each genome's species index.
+ */
+pub fn assign_species(
+    genomes: &[NeatGenome],
+    representatives: &mut Vec<NeatGenome>,
+    threshold: f64,
+    c: &DistanceCoefficients,
+) -> Vec<usize> {
+    genomes
+        .iter()
+        .map(|g| {
+            match representatives
+                .iter()
+                .position(|r| r.distance(g, c) < threshold)
+            {
+                Some(s) => s,
+                None => {
+                    representatives.push(g.clone());
+                    representatives.len() - 1
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Input 0, control 1, output 2, plus control 9 in genomes whose connections use it
+    fn genome(genes: &[(Innovation, NeuronId, NeuronId, f64)]) -> NeatGenome {
+        let mut nodes = BTreeMap::new();
+        for (id, t) in [
+            (0, NeuronType::Input),
+            (1, NeuronType::Control),
+            (2, NeuronType::Output),
+        ] {
+            nodes.insert(
+                id,
+                NodeGene {
+                    neuron: Neuron::new(id, 0.0, 0.0),
+                    neuron_type: t,
+                },
+            );
+        }
+        if genes.iter().any(|g| g.1 == 9 || g.2 == 9) {
+            nodes.insert(
+                9,
+                NodeGene {
+                    neuron: Neuron::new(9, 0.0, 0.0),
+                    neuron_type: NeuronType::Control,
+                },
+            );
+        }
+        NeatGenome {
+            nodes,
+            connections: genes
+                .iter()
+                .map(|(innovation, source, target, weight)| ConnectionGene {
+                    innovation: *innovation,
+                    source: *source,
+                    target: *target,
+                    weight: *weight,
+                    enabled: true,
+                })
+                .collect(),
+            labeled_neurons: HashMap::new(),
+        }
+    }
+
+    // Innovations 0 and 2 match; 1 and 3 are disjoint, 5 is excess
+    fn parents() -> (NeatGenome, NeatGenome) {
+        let fitter = genome(&[
+            (0, 0, 1, 1.0),
+            (1, 0, 2, 1.0),
+            (2, 1, 2, 1.0),
+            (5, 1, 1, 1.0),
+        ]);
+        let other = genome(&[(0, 0, 1, 2.0), (2, 1, 2, 0.0), (3, 0, 9, 1.0)]);
+        (fitter, other)
+    }
+
+    fn coefficients(excess: f64, disjoint: f64, weight: f64) -> DistanceCoefficients {
+        DistanceCoefficients {
+            excess,
+            disjoint,
+            weight,
+        }
+    }
+
+    #[test]
+    fn distance_tells_excess_from_disjoint_genes() {
+        let (a, b) = parents();
+        // N is the larger genome's 4 connections
+        assert_eq!(a.distance(&b, &coefficients(1.0, 0.0, 0.0)), 1.0 / 4.0);
+        assert_eq!(a.distance(&b, &coefficients(0.0, 1.0, 0.0)), 2.0 / 4.0);
+        assert_eq!(b.distance(&a, &coefficients(1.0, 0.0, 0.0)), 1.0 / 4.0);
+        // Weights of the matching genes differ by 1 each
+        assert_eq!(a.distance(&b, &coefficients(0.0, 0.0, 1.0)), 1.0);
+        assert_eq!(a.distance(&a, &DistanceCoefficients::default()), 0.0);
+    }
+
+    #[test]
+    fn crossover_takes_unmatched_genes_from_the_fitter_parent() {
+        let mut rng = rand::thread_rng();
+        let (fitter, other) = parents();
+        let mut weights = Vec::new();
+        for _ in 0..50 {
+            let child = NeatGenome::crossover(&fitter, &other, &mut rng);
+            let innovations = child
+                .connections
+                .iter()
+                .map(|c| c.innovation)
+                .collect::<Vec<Innovation>>();
+            assert_eq!(innovations, vec![0, 1, 2, 5]);
+            assert_eq!(child.connections[1], fitter.connections[1]);
+            assert_eq!(child.connections[3], fitter.connections[3]);
+            assert!(!child.nodes.contains_key(&9));
+            weights.push(child.connections[0].weight);
+        }
+        // Matching genes come from either parent
+        assert!(weights.contains(&1.0) && weights.contains(&2.0));
+    }
+
+    // Input 0 connected to output 1 with weight 0.7
+    fn brain() -> Brain {
+        let mut brain = Brain::new();
+        brain.add_neuron(Neuron::new(0, 0.0, 0.0), NeuronType::Input, Some("in"));
+        brain.add_neuron(Neuron::new(1, 0.0, 0.0), NeuronType::Output, Some("out"));
+        brain.add_edge(Edge::new(0, 1, 0.7));
+        brain
+    }
+
+    fn gene(
+        innovation: Innovation,
+        source: NeuronId,
+        target: NeuronId,
+        weight: f64,
+        enabled: bool,
+    ) -> ConnectionGene {
+        ConnectionGene {
+            innovation,
+            source,
+            target,
+            weight,
+            enabled,
+        }
+    }
+
+    #[test]
+    fn splitting_routes_the_connection_through_a_new_neuron() {
+        let mut rng = rand::thread_rng();
+        let mut innovations = Innovations::new();
+        let mut a = NeatGenome::from_brain(&brain(), &mut innovations);
+        let mut b = a.clone();
+        assert_eq!(
+            a.mutate_split_connection(&mut innovations, &mut rng),
+            Some(2)
+        );
+        assert_eq!(a.nodes[&2].neuron_type, NeuronType::Control);
+        assert_eq!(
+            a.connections,
+            vec![
+                gene(0, 0, 1, 0.7, false),
+                gene(1, 0, 2, 1.0, true),
+                gene(2, 2, 1, 0.7, true)
+            ]
+        );
+
+        // The same split elsewhere gets the same node and innovations
+        assert_eq!(
+            b.mutate_split_connection(&mut innovations, &mut rng),
+            Some(2)
+        );
+        assert_eq!(a, b);
+
+        // Only the already split connection is left to split
+        a.connections[0].enabled = true;
+        a.connections[1].enabled = false;
+        a.connections[2].enabled = false;
+        assert_eq!(a.mutate_split_connection(&mut innovations, &mut rng), None);
+    }
+
+    #[test]
+    fn hand_built_genomes_split_into_a_fresh_node() {
+        let mut rng = rand::thread_rng();
+        let mut innovations = Innovations::new();
+        assert_eq!(innovations.connection(0, 2), 0);
+        let mut g = genome(&[(0, 0, 2, 0.7)]);
+        assert_eq!(
+            g.mutate_split_connection(&mut innovations, &mut rng),
+            Some(3)
+        );
+        assert_eq!(g.nodes.len(), 4);
+    }
+
+    #[test]
+    fn add_connection_stops_once_every_pair_is_connected() {
+        let mut rng = rand::thread_rng();
+        let mut innovations = Innovations::new();
+        let mut g = NeatGenome::from_brain(&brain(), &mut innovations);
+        assert!(!g.mutate_add_connection(&mut innovations, &mut rng));
+
+        // With a control neuron 2 routing 0 -> 2 -> 1, 0 -> 1 and 2 -> 2 are free
+        g.mutate_split_connection(&mut innovations, &mut rng);
+        g.connections.retain(|c| c.enabled);
+        let mut added = 0;
+        while g.mutate_add_connection(&mut innovations, &mut rng) {
+            added += 1;
+        }
+        assert_eq!(added, 2);
+        assert_eq!(g.connections.len(), 4);
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use super::NeuronId;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Neuron {
+    pub id: NeuronId,
+    pub bias: f64,
+    // Fraction of the last output carried into the next activation
+    pub reset_factor: f64,
+    // Run state, not part of the stored format
+    #[serde(skip)]
+    pub activation: f64,
+}
+
+impl Neuron {
+    pub fn new(id: NeuronId, bias: f64, reset_factor: f64) -> Self {
+        Neuron {
+            id,
+            bias,
+            reset_factor,
+            activation: 0.0,
+        }
+    }
+
+    // ReLU clamped to [0, 1] of the summed inputs, bias and carried activation
+    pub fn activate(&mut self, input_signals: &[f64]) -> f64 {
+        let total_input = input_signals.iter().sum::<f64>() + self.bias + self.activation;
+        // v2's min(max(0.0, x), 1.0) maps NaN to 0, clamp alone would keep it
+        let output = if total_input.is_nan() {
+            0.0
+        } else {
+            total_input.clamp(0.0, 1.0)
+        };
+        self.activation = output * self.reset_factor;
+        output
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edge {
+    pub source: NeuronId,
+    pub target: NeuronId,
+    pub weight: f64,
+}
+
+impl Edge {
+    pub fn new(source: NeuronId, target: NeuronId, weight: f64) -> Self {
+        Edge {
+            source,
+            target,
+            weight,
+        }
+    }
+
+    pub fn transmit(&self, signal: f64) -> f64 {
+        signal * self.weight
+    }
+}
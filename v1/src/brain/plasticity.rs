@@ -0,0 +1,253 @@
+// Within-lifetime plasticity: edge weights that change as the brain runs
+//
+// After every step each edge's weight moves by the rule's change for its source's output
+// before the step (pre) and its target's output after it (post), the pair the edge just
+// connected. With a modulator neuron the change is also multiplied by that neuron's output,
+// so learning happens only when the brain signals it should, e.g. on reward. This generalizes
+// e10's learning_factor: learning_rate plays its part, now for every edge of a network.
+//
+// Changed weights are the brain's own; a child developing from its genome starts from the
+// genome's weights again.
+
+use std::collections::BTreeMap;
+
+use crate::evol_prim::Base::*;
+use crate::evol_prim::*;
+use crate::genes::GeneSchema;
+
+use super::{Brain, NeuronId};
+
+// Plastic weights are kept within [-MAX_PLASTIC_WEIGHT, MAX_PLASTIC_WEIGHT]
+pub const MAX_PLASTIC_WEIGHT: f64 = 4.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlasticityRule {
+    // pre * post
+    Hebbian,
+    // post * (pre - post * weight), Hebbian with weight decay that keeps weights bounded
+    Oja,
+    // a * pre * post + b * pre + c * post + d
+    Abcd { a: f64, b: f64, c: f64, d: f64 },
+}
+
+impl PlasticityRule {
+    // Weight change before the learning rate and modulation
+    pub fn change(&self, pre: f64, post: f64, weight: f64) -> f64 {
+        match *self {
+            PlasticityRule::Hebbian => pre * post,
+            PlasticityRule::Oja => post * (pre - post * weight),
+            PlasticityRule::Abcd { a, b, c, d } => a * pre * post + b * pre + c * post + d,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plasticity {
+    pub rule: PlasticityRule,
+    pub learning_rate: f64,
+    // Neuron whose output gates every change; None for unmodulated plasticity
+    pub modulator: Option<NeuronId>,
+}
+
+impl Plasticity {
+    /**
+     * Update every edge for one step of brain, given the neuron outputs before (the values
+     * passed to Brain::step) and after it (what it returned).
+     */
+    pub fn update(
+        &self,
+        brain: &mut Brain,
+        pre: &BTreeMap<NeuronId, f64>,
+        post: &BTreeMap<NeuronId, f64>,
+    ) {
+        let modulation = match self.modulator {
+            Some(id) => *post.get(&id).unwrap_or(&0.0),
+            None => 1.0,
+        };
+        if modulation == 0.0 || self.learning_rate == 0.0 {
+            return;
+        }
+        for edge in &mut brain.edges {
+            let x = *pre.get(&edge.source).unwrap_or(&0.0);
+            let y = *post.get(&edge.target).unwrap_or(&0.0);
+            let change = self.learning_rate * modulation * self.rule.change(x, y, edge.weight);
+            edge.weight = (edge.weight + change).clamp(-MAX_PLASTIC_WEIGHT, MAX_PLASTIC_WEIGHT);
+        }
+    }
+
+    // Brain::process_n, with an update after every step
+    pub fn process_n(
+        &self,
+        brain: &mut Brain,
+        input_neuron_values: &[(&str, f64)],
+        n: usize,
+    ) -> BTreeMap<NeuronId, f64> {
+        let mut neuron_values = brain.labeled_inputs(input_neuron_values);
+        for _ in 0..n {
+            let next = brain.step(&neuron_values);
+            self.update(brain, &neuron_values, &next);
+            neuron_values = next;
+        }
+        neuron_values
+    }
+}
+
+/**
+ * A plasticity gene: CATC, a tag (A Hebbian, C Oja, T ABCD), then the learning rate and ABCD's
+ * a, b, c, d as bytes mapped to [-1, 1], two bases choosing the modulator, and GGG. The
+ * modulator number is 0 for none, otherwise k picks control neuron k - 1 (modulo their
+ * number) of the brain the gene acts on. Its start codon differs from
+ * development::DEVELOPMENT_SCHEMA's, so both kinds of gene can share a genome.
+ */
+pub const PLASTICITY_SCHEMA: GeneSchema = GeneSchema {
+    start: &[C, A, T, C],
+    stop: &[G, G, G],
+    payload_len: 22,
+    kinds: 3,
+};
+
+fn byte_at(payload: &[Base], at: usize) -> f64 {
+    byte_to_feature_space(read4_bases_to_unsigned_byte(
+        &mut payload[at..at + 4].iter(),
+    )) as f64
+}
+
+/**
+ * The plasticity the first functional plasticity gene in seq gives brain, None if there is
+ * none. Brains without plasticity genes keep their weights.
+ */
+pub fn decode(seq: &[Base], brain: &Brain) -> Option<Plasticity> {
+    let genes = PLASTICITY_SCHEMA.decode(seq);
+    let gene = genes.iter().find(|g| g.functional)?;
+    let p = &gene.payload;
+    let rule = match gene.kind {
+        0 => PlasticityRule::Hebbian,
+        1 => PlasticityRule::Oja,
+        _ => PlasticityRule::Abcd {
+            a: byte_at(p, 4),
+            b: byte_at(p, 8),
+            c: byte_at(p, 12),
+            d: byte_at(p, 16),
+        },
+    };
+    let controls = &brain.control_neuron_ids;
+    let modulator = match p[20] as usize * 4 + p[21] as usize {
+        0 => None,
+        _ if controls.is_empty() => None,
+        k => Some(controls[(k - 1) % controls.len()]),
+    };
+    Some(Plasticity {
+        rule,
+        learning_rate: byte_at(p, 0),
+        modulator,
+    })
+}
+
+// Gene for decode, e.g. for hand-written ancestors. modulator is the number decode reads.
+pub fn plasticity_gene(rule: PlasticityRule, learning_rate: f32, modulator: usize) -> BaseSeq {
+    let (kind, coefficients) = match rule {
+        PlasticityRule::Hebbian => (0, [0.0; 4]),
+        PlasticityRule::Oja => (1, [0.0; 4]),
+        PlasticityRule::Abcd { a, b, c, d } => (2, [a, b, c, d]),
+    };
+    let mut payload = Vec::new();
+    payload.extend(unsigned_byte_to_4_bases(feature_space_to_byte(
+        learning_rate,
+    )));
+    for v in coefficients {
+        payload.extend(unsigned_byte_to_4_bases(feature_space_to_byte(v as f32)));
+    }
+    payload.push(BASES[(modulator / 4) % 4]);
+    payload.push(BASES[modulator % 4]);
+    PLASTICITY_SCHEMA.encode(kind, &payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::{Edge, Neuron, NeuronType};
+
+    // Input 0 feeds output 1 with weight 0.5; control 2 can modulate
+    fn brain() -> Brain {
+        let mut brain = Brain::new();
+        brain.add_neuron(Neuron::new(0, 0.0, 0.0), NeuronType::Input, Some("in"));
+        brain.add_neuron(Neuron::new(1, 0.0, 0.0), NeuronType::Output, Some("out"));
+        brain.add_neuron(Neuron::new(2, 0.0, 0.0), NeuronType::Control, None);
+        brain.add_edge(Edge::new(0, 1, 0.5));
+        brain
+    }
+
+    fn values(v: &[(NeuronId, f64)]) -> BTreeMap<NeuronId, f64> {
+        v.iter().copied().collect()
+    }
+
+    fn plasticity(rule: PlasticityRule, modulator: Option<NeuronId>) -> Plasticity {
+        Plasticity {
+            rule,
+            learning_rate: 0.1,
+            modulator,
+        }
+    }
+
+    #[test]
+    fn hebbian_and_oja_move_weights_by_pre_and_post() {
+        let (pre, post) = (values(&[(0, 1.0)]), values(&[(1, 0.5)]));
+        let mut b = brain();
+        plasticity(PlasticityRule::Hebbian, None).update(&mut b, &pre, &post);
+        assert!((b.edges[0].weight - 0.55).abs() < 1e-12);
+
+        // 0.5 * (1 - 0.5 * 0.5)
+        let mut b = brain();
+        plasticity(PlasticityRule::Oja, None).update(&mut b, &pre, &post);
+        assert!((b.edges[0].weight - 0.5375).abs() < 1e-12);
+    }
+
+    #[test]
+    fn oja_stays_bounded_where_hebbian_saturates() {
+        let (pre, post) = (values(&[(0, 1.0)]), values(&[(1, 1.0)]));
+        let (mut hebbian, mut oja) = (brain(), brain());
+        for _ in 0..200 {
+            plasticity(PlasticityRule::Hebbian, None).update(&mut hebbian, &pre, &post);
+            plasticity(PlasticityRule::Oja, None).update(&mut oja, &pre, &post);
+        }
+        assert_eq!(hebbian.edges[0].weight, MAX_PLASTIC_WEIGHT);
+        // Oja settles where pre = post * weight
+        assert!((oja.edges[0].weight - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn modulator_gates_the_change() {
+        let pre = values(&[(0, 1.0)]);
+        let rule = plasticity(PlasticityRule::Hebbian, Some(2));
+        let mut b = brain();
+        rule.update(&mut b, &pre, &values(&[(1, 1.0), (2, 0.0)]));
+        assert_eq!(b.edges[0].weight, 0.5);
+        rule.update(&mut b, &pre, &values(&[(1, 1.0), (2, 0.5)]));
+        assert!((b.edges[0].weight - 0.55).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gene_decodes_to_its_rule() {
+        let b = brain();
+        let rule = PlasticityRule::Abcd {
+            a: 0.5,
+            b: -0.25,
+            c: 0.0,
+            d: 0.125,
+        };
+        let seq = [vec![A; 5], plasticity_gene(rule, 0.25, 1), vec![T; 5]].concat();
+        let expected = Plasticity {
+            rule,
+            learning_rate: 0.25,
+            // Modulator 1 is the first control neuron
+            modulator: Some(2),
+        };
+        assert_eq!(decode(&seq, &b), Some(expected));
+        let seq = plasticity_gene(PlasticityRule::Oja, 0.5, 0);
+        assert_eq!(
+            decode(&seq, &b).map(|p| (p.rule, p.modulator)),
+            Some((PlasticityRule::Oja, None))
+        );
+        assert_eq!(decode(&[C; 30], &b), None);
+    }
+}
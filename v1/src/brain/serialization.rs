@@ -0,0 +1,132 @@
+// v2's stored_organisms JSON format, so populations can move between the Python and Rust
+// engines: {"samples": [{"brain": {neurons, edges, input_neuron_ids, control_neuron_ids,
+// output_neuron_ids, labeled_neurons}}]}. Neuron activations are not stored.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::Brain;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredOrganism {
+    pub brain: Brain,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Samples {
+    pub samples: Vec<StoredOrganism>,
+}
+
+impl Samples {
+    pub fn from_brains(brains: impl IntoIterator<Item = Brain>) -> Self {
+        Samples {
+            samples: brains
+                .into_iter()
+                .map(|brain| StoredOrganism { brain })
+                .collect(),
+        }
+    }
+
+    pub fn into_brains(self) -> Vec<Brain> {
+        self.samples.into_iter().map(|s| s.brain).collect()
+    }
+
+    pub fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    // Indented by 4 spaces, like the checked-in v2 samples
+    pub fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+        let mut serializer = serde_json::Serializer::with_formatter(writer, formatter);
+        self.serialize(&mut serializer)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Samples::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+}
+
+// Brain::neurons is keyed by id, v2 stores a list of neurons
+pub mod neuron_list {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::brain::{Neuron, NeuronId};
+
+    pub fn serialize<S: Serializer>(
+        neurons: &BTreeMap<NeuronId, Neuron>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(neurons.values())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<NeuronId, Neuron>, D::Error> {
+        let neurons = Vec::<Neuron>::deserialize(deserializer)?;
+        Ok(neurons.into_iter().map(|n| (n.id, n)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample0_path() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../v2/py/src/stored_organisms/sample0.json")
+    }
+
+    #[test]
+    fn loads_sample0() {
+        let samples = Samples::load(sample0_path()).unwrap();
+        assert_eq!(samples.samples.len(), 100);
+        let brain = &samples.samples[0].brain;
+        assert_eq!(brain.label_id("input_bad_food"), Some(0));
+        assert_eq!(brain.label_id("output_eat"), Some(3));
+        assert!(brain.neurons.values().all(|n| n.activation == 0.0));
+        assert!(brain.edges.iter().all(
+            |e| brain.neurons.contains_key(&e.source) && brain.neurons.contains_key(&e.target)
+        ));
+    }
+
+    #[test]
+    fn round_trips_sample0_json() {
+        let text = std::fs::read_to_string(sample0_path()).unwrap();
+        let original: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let samples: Samples = serde_json::from_str(&text).unwrap();
+        assert_eq!(serde_json::to_value(&samples).unwrap(), original);
+    }
+
+    #[test]
+    fn round_trips_through_save_and_load() {
+        let samples = Samples::load(sample0_path()).unwrap();
+        let mut written = Vec::new();
+        samples.write_to(&mut written).unwrap();
+        let reloaded = Samples::read_from(&mut written.as_slice()).unwrap();
+        assert_eq!(reloaded, samples);
+    }
+
+    #[test]
+    fn labeled_neurons_are_optional() {
+        let text = r#"{"samples": [{"brain": {"neurons": [{"id": 0, "bias": 0.5, "reset_factor": 0.0}],
+            "edges": [], "input_neuron_ids": [0], "control_neuron_ids": [], "output_neuron_ids": []}}]}"#;
+        let brains = Samples::read_from(&mut text.as_bytes())
+            .unwrap()
+            .into_brains();
+        assert_eq!(brains.len(), 1);
+        assert!(brains[0].labeled_neurons.is_empty());
+        assert_eq!(brains[0].neurons[&0].bias, 0.5);
+    }
+}
@@ -0,0 +1,492 @@
+// Artificial chemistry substrate for the primordial soup
+//
+// A well-mixed pool of sequences (molecules) with concentrations, instead of organisms.
+// Molecules pair base-by-base with their complement (A-T, C-G). Strands are treated as
+// parallel (no reversal) to keep the rules simple.
+//   Binding: a molecule colliding with a template whose complement shares at least
+//     min_binding bases with it forms a duplex, protecting both from cleavage that step.
+//   Ligation: two strands bound side by side on the same template (each over at least
+//     min_binding bases, meeting at the junction) are joined. The template is released
+//     unchanged, i.e. it catalysed the ligation.
+//   Spontaneous ligation: any other collision joins the two strands with probability
+//     spontaneous_ligation_prob, which is what first produces molecules long enough to
+//     act as templates.
+//   Cleavage: unbound molecules break at each bond with probability cleave_prob per step.
+// Because a ligation product contains the complement of its template, it can in turn
+// template the formation of its template's pieces, so autocatalytic sets can emerge.
+
+use std::collections::{HashMap, HashSet};
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::evol_prim::Base::*;
+use crate::evol_prim::*;
+
+pub fn complement(b: Base) -> Base {
+    match b {
+        A => T,
+        T => A,
+        C => G,
+        G => C,
+    }
+}
+
+pub fn complement_seq(seq: &[Base]) -> BaseSeq {
+    seq.iter().map(|b| complement(*b)).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct ChemistryParams {
+    // Random oligomers added per step, lengths uniform in [1, influx_max_len]
+    pub influx: usize,
+    pub influx_max_len: usize,
+    // Template/strand collisions tried per step
+    pub collisions: usize,
+    pub min_binding: usize,
+    pub spontaneous_ligation_prob: f32,
+    pub cleave_prob: f32,
+    // Total molecule count is diluted back down to this after each step
+    pub capacity: usize,
+    // Ligations from this many most recent steps count towards catalysis
+    pub catalysis_window: usize,
+    // Motif whose first appearance is reported, e.g. E1_REPRODUCE_PREFIX
+    pub replicator: &'static [Base],
+}
+
+#[derive(Debug, Clone)]
+pub struct ChemistryCensus {
+    pub t: i32,
+    pub molecules: usize,
+    pub species: usize,
+    pub mean_length: f32,
+    pub ligations: usize,
+    pub cleavages: usize,
+    pub autocatalytic_set_size: usize,
+    // Most concentrated species and their concentration (fraction of all molecules)
+    pub top: Vec<(BaseSeq, f32)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chemistry {
+    pub params: ChemistryParams,
+    // Sequence -> number of molecules
+    pub pool: HashMap<BaseSeq, usize>,
+    pub t: i32,
+    // Per recent step: product -> templates that catalysed its ligation
+    pub catalysis: Vec<HashMap<BaseSeq, HashSet<BaseSeq>>>,
+    pub history: Vec<ChemistryCensus>,
+    // (step, sequence) of the first molecule carrying the replicator motif
+    pub replicator_detection: Option<(i32, BaseSeq)>,
+}
+
+// Number of most concentrated species reported per census
+const CENSUS_TOP: usize = 5;
+
+/**
+ * Split point k such that complement(template[..k]) is the suffix of left and
+ * complement(template[k..]) is the prefix of right, each at least min_binding long.
+ */
+fn ligation_site(
+    template: &[Base],
+    left: &[Base],
+    right: &[Base],
+    min_binding: usize,
+) -> Option<usize> {
+    let min_binding = min_binding.max(1);
+    let paired = complement_seq(template);
+    (min_binding..=paired.len().saturating_sub(min_binding))
+        .find(|k| left.ends_with(&paired[..*k]) && right.starts_with(&paired[*k..]))
+}
+
+// Whether some stretch of at least min_binding bases of seq pairs with the template
+fn binds(template: &[Base], seq: &[Base], min_binding: usize) -> bool {
+    let min_binding = min_binding.max(1);
+    if template.len() < min_binding || seq.len() < min_binding {
+        return false;
+    }
+    let paired = complement_seq(template);
+    paired
+        .windows(min_binding)
+        .any(|w| seq.windows(min_binding).any(|s| s == w))
+}
+
+impl Chemistry {
+    pub fn new(params: ChemistryParams) -> Self {
+        Chemistry {
+            params,
+            pool: HashMap::new(),
+            t: 0,
+            catalysis: Vec::new(),
+            history: Vec::new(),
+            replicator_detection: None,
+        }
+    }
+
+    pub fn total_molecules(&self) -> usize {
+        self.pool.values().sum()
+    }
+
+    pub fn concentration(&self, seq: &BaseSeq) -> f32 {
+        let total = self.total_molecules();
+        if total == 0 {
+            0.0
+        } else {
+            *self.pool.get(seq).unwrap_or(&0) as f32 / total as f32
+        }
+    }
+
+    fn add(&mut self, seq: BaseSeq, n: usize) {
+        if !seq.is_empty() && n > 0 {
+            *self.pool.entry(seq).or_insert(0) += n;
+        }
+    }
+
+    // Returns false if no such molecule is left
+    fn remove(&mut self, seq: &BaseSeq) -> bool {
+        match self.pool.get_mut(seq) {
+            Some(n) if *n > 1 => {
+                *n -= 1;
+                true
+            }
+            Some(_) => {
+                self.pool.remove(seq);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // All molecules, one entry per molecule, for sampling proportional to concentration
+    fn molecules(&self) -> Vec<BaseSeq> {
+        self.pool
+            .iter()
+            .flat_map(|(s, n)| std::iter::repeat_n(s, *n).cloned())
+            .collect()
+    }
+
+    pub fn step(&mut self, rng: &mut ThreadRng) {
+        // Influx
+        for _ in 0..self.params.influx {
+            let len = rng.gen_range(1..=self.params.influx_max_len.max(1));
+            self.add((0..len).map(|_| rng.gen()).collect(), 1);
+        }
+
+        // Collisions: binding and template-directed ligation
+        let mut molecules = self.molecules();
+        let mut bound: HashMap<BaseSeq, usize> = HashMap::new();
+        let mut catalysis: HashMap<BaseSeq, HashSet<BaseSeq>> = HashMap::new();
+        let mut ligations = 0;
+        if molecules.len() >= 3 {
+            for _ in 0..self.params.collisions {
+                let pick = |rng: &mut ThreadRng| rng.gen_range(0..molecules.len());
+                let (ti, li, ri) = (pick(rng), pick(rng), pick(rng));
+                if ti == li || ti == ri || li == ri {
+                    continue;
+                }
+                let (template, left, right) = (&molecules[ti], &molecules[li], &molecules[ri]);
+                let templated =
+                    ligation_site(template, left, right, self.params.min_binding).is_some();
+                if templated || rng.gen::<f32>() < self.params.spontaneous_ligation_prob {
+                    let (template, left, right) = (template.clone(), left.clone(), right.clone());
+                    // Earlier collisions this step may already have used these molecules up
+                    let available = self.pool.get(&left).is_some_and(|n| *n > 0)
+                        && self
+                            .pool
+                            .get(&right)
+                            .is_some_and(|n| *n > (left == right) as usize);
+                    if available {
+                        self.remove(&left);
+                        self.remove(&right);
+                        let product = [left, right].concat();
+                        self.add(product.clone(), 1);
+                        if templated {
+                            catalysis
+                                .entry(product.clone())
+                                .or_default()
+                                .insert(template);
+                            // The product stays on the template this step
+                            *bound.entry(product.clone()).or_insert(0) += 1;
+                        }
+                        molecules[li] = product;
+                        ligations += 1;
+                    }
+                } else if binds(template, left, self.params.min_binding) {
+                    *bound.entry(template.clone()).or_insert(0) += 1;
+                    *bound.entry(left.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+
+        // Cleavage of unbound molecules
+        let mut cleavages = 0;
+        let pool = std::mem::take(&mut self.pool);
+        for (seq, n) in pool {
+            let protected = (*bound.get(&seq).unwrap_or(&0)).min(n);
+            self.add(seq.clone(), protected);
+            for _ in protected..n {
+                let bonds = seq.len().saturating_sub(1);
+                let cut = (0..bonds).find(|_| rng.gen::<f32>() < self.params.cleave_prob);
+                match cut {
+                    Some(i) => {
+                        self.add(seq[..=i].to_vec(), 1);
+                        self.add(seq[i + 1..].to_vec(), 1);
+                        cleavages += 1;
+                    }
+                    None => self.add(seq.clone(), 1),
+                }
+            }
+        }
+
+        // Outflow
+        let total = self.total_molecules();
+        if total > self.params.capacity {
+            let mut molecules = self.molecules();
+            for _ in 0..total - self.params.capacity {
+                let i = rng.gen_range(0..molecules.len());
+                let seq = molecules.swap_remove(i);
+                self.remove(&seq);
+            }
+        }
+
+        self.catalysis.push(catalysis);
+        if self.catalysis.len() > self.params.catalysis_window.max(1) {
+            self.catalysis.remove(0);
+        }
+
+        if self.replicator_detection.is_none() {
+            if let Some(seq) = self
+                .pool
+                .keys()
+                .find(|s| s.starts_with(self.params.replicator))
+            {
+                println!(
+                    "Replicator {} first appeared at step {}: {}",
+                    seq_to_string(self.params.replicator),
+                    self.t,
+                    seq_to_string(seq)
+                );
+                self.replicator_detection = Some((self.t, seq.clone()));
+            }
+        }
+
+        self.census(ligations, cleavages);
+        self.t += 1;
+    }
+
+    /**
+     * Largest set of present species, each produced by ligation and catalysed by a template
+     * that is itself in the set, over the recent catalysis window. Starts from every present
+     * ligation product and repeatedly drops those without a catalyst left in the set.
+     */
+    pub fn autocatalytic_set(&self) -> HashSet<BaseSeq> {
+        let mut catalysts: HashMap<&BaseSeq, HashSet<&BaseSeq>> = HashMap::new();
+        for step in &self.catalysis {
+            for (product, templates) in step {
+                catalysts
+                    .entry(product)
+                    .or_default()
+                    .extend(templates.iter());
+            }
+        }
+        let mut set = catalysts
+            .keys()
+            .filter(|p| self.pool.contains_key(**p))
+            .copied()
+            .collect::<HashSet<&BaseSeq>>();
+        loop {
+            let closed = set
+                .iter()
+                .filter(|p| catalysts[**p].iter().any(|c| set.contains(c)))
+                .copied()
+                .collect::<HashSet<&BaseSeq>>();
+            if closed.len() == set.len() {
+                break;
+            }
+            set = closed;
+        }
+        set.into_iter().cloned().collect()
+    }
+
+    fn census(&mut self, ligations: usize, cleavages: usize) {
+        let molecules = self.total_molecules();
+        let mean_length = if molecules == 0 {
+            0.0
+        } else {
+            self.pool.iter().map(|(s, n)| s.len() * n).sum::<usize>() as f32 / molecules as f32
+        };
+        let mut top = self
+            .pool
+            .iter()
+            .map(|(s, n)| (s.clone(), *n as f32 / molecules as f32))
+            .collect::<Vec<(BaseSeq, f32)>>();
+        top.sort_by(|a, b| b.1.total_cmp(&a.1));
+        top.truncate(CENSUS_TOP);
+
+        self.history.push(ChemistryCensus {
+            t: self.t,
+            molecules,
+            species: self.pool.len(),
+            mean_length,
+            ligations,
+            cleavages,
+            autocatalytic_set_size: self.autocatalytic_set().len(),
+            top,
+        });
+    }
+
+    // Sequences to seed an organism-based run (e.g. soup or E1) with, one per molecule
+    pub fn to_population(&self) -> Vec<BaseSeq> {
+        self.molecules()
+    }
+}
+
+impl std::fmt::Display for ChemistryCensus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "t {}, molecules {}, species {}, mean length {:.2}, ligations {}, cleavages {}, autocatalytic set {}, top [",
+            self.t,
+            self.molecules,
+            self.species,
+            self.mean_length,
+            self.ligations,
+            self.cleavages,
+            self.autocatalytic_set_size
+        )?;
+        for (i, (seq, c)) in self.top.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}:{:.3}", seq_to_string(seq), c)?;
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> ChemistryParams {
+        ChemistryParams {
+            influx: 0,
+            influx_max_len: 1,
+            collisions: 0,
+            min_binding: 2,
+            spontaneous_ligation_prob: 0.0,
+            cleave_prob: 0.0,
+            capacity: 1000,
+            catalysis_window: 2,
+            replicator: &[A, T, A, T],
+        }
+    }
+
+    #[test]
+    fn ligation_needs_both_strands_paired_at_the_junction() {
+        // The template ACGT pairs with TG then CA
+        let template = [A, C, G, T];
+        assert_eq!(ligation_site(&template, &[C, T, G], &[C, A, A], 2), Some(2));
+        assert_eq!(ligation_site(&template, &[T], &[G, C, A], 1), Some(1));
+        // Too short a pairing on the left, or a mismatch on the right
+        assert_eq!(ligation_site(&template, &[C, T, G], &[C, A, A], 3), None);
+        assert_eq!(ligation_site(&template, &[C, T, G], &[C, C], 2), None);
+        assert_eq!(ligation_site(&template, &[C, A], &[T, G], 2), None);
+    }
+
+    #[test]
+    fn autocatalytic_set_keeps_products_catalysed_from_within() {
+        let (x, y, z, w, q, v) = (
+            vec![A, A],
+            vec![T, T],
+            vec![C, C],
+            vec![G, G],
+            vec![A, C],
+            vec![G, T],
+        );
+        let mut chem = Chemistry::new(params());
+        for s in [&x, &y, &z, &w, &q] {
+            chem.add(s.clone(), 1);
+        }
+        let catalysed = |pairs: &[(&BaseSeq, &BaseSeq)]| {
+            let mut step: HashMap<BaseSeq, HashSet<BaseSeq>> = HashMap::new();
+            for (product, template) in pairs {
+                step.entry((*product).clone())
+                    .or_default()
+                    .insert((*template).clone());
+            }
+            step
+        };
+        // x and y make each other and x makes q; z is made by w, which nothing in the set
+        // makes, and v is no longer in the pool
+        chem.catalysis = vec![
+            catalysed(&[(&x, &y), (&z, &w)]),
+            catalysed(&[(&y, &x), (&q, &x), (&v, &x)]),
+        ];
+        let set = chem.autocatalytic_set();
+        assert_eq!(set, HashSet::from([x.clone(), y.clone(), q.clone()]));
+
+        // Without y's ligation the cycle, and with it the whole set, falls apart
+        chem.catalysis.remove(1);
+        assert!(chem.autocatalytic_set().is_empty());
+    }
+
+    #[test]
+    fn unbound_molecules_are_cleaved_and_diluted_to_capacity() {
+        let mut rng = rand::thread_rng();
+        let mut chem = Chemistry::new(ChemistryParams {
+            cleave_prob: 1.0,
+            capacity: 4,
+            replicator: &[C, G],
+            ..params()
+        });
+        chem.add(vec![A, C, G, T], 3);
+        chem.step(&mut rng);
+        // Each ACGT breaks at its first bond into A and CGT; 2 of the 6 pieces flow out
+        assert_eq!(chem.total_molecules(), 4);
+        assert!(chem
+            .pool
+            .keys()
+            .all(|s| *s == vec![A] || *s == vec![C, G, T]));
+        assert_eq!(chem.history[0].cleavages, 3);
+        assert_eq!(chem.replicator_detection, Some((0, vec![C, G, T])));
+    }
+
+    #[test]
+    fn bound_molecules_escape_cleavage() {
+        let mut rng = rand::thread_rng();
+        // AAAA and TTTT pair whenever they collide, CC pairs with neither
+        let mut chem = Chemistry::new(ChemistryParams {
+            collisions: 200,
+            cleave_prob: 1.0,
+            ..params()
+        });
+        for s in [vec![A; 4], vec![T; 4], vec![C, C]] {
+            chem.add(s, 1);
+        }
+        chem.step(&mut rng);
+        let expected = HashMap::from([(vec![A; 4], 1), (vec![T; 4], 1), (vec![C], 2)]);
+        assert_eq!(chem.pool, expected);
+    }
+
+    #[test]
+    fn templated_ligation_is_recorded_as_catalysis() {
+        let mut rng = rand::thread_rng();
+        // TGCA pairs with AC then GT, so it joins them into ACGT
+        let mut chem = Chemistry::new(ChemistryParams {
+            collisions: 200,
+            ..params()
+        });
+        for s in [vec![T, G, C, A], vec![A, C], vec![G, T]] {
+            chem.add(s, 1);
+        }
+        chem.step(&mut rng);
+        assert_eq!(
+            chem.pool,
+            HashMap::from([(vec![T, G, C, A], 1), (vec![A, C, G, T], 1)])
+        );
+        assert_eq!(chem.history[0].ligations, 1);
+        let expected = HashMap::from([(vec![A, C, G, T], HashSet::from([vec![T, G, C, A]]))]);
+        assert_eq!(chem.catalysis, vec![expected]);
+    }
+}
@@ -0,0 +1,376 @@
+// Diploid genomes: two homologous sequences, dominance, meiosis with crossover
+
+use std::collections::HashMap;
+
+use rand::prelude::{SliceRandom, ThreadRng};
+use rand::Rng;
+
+use crate::evol_prim::*;
+
+#[derive(Debug, Clone)]
+pub struct DiploidOrganism<O> {
+    pub homologs: [BaseSeq; 2],
+    // Expressed (haploid) view of the homologs, so experiment functions written against
+    // Organism<O> can be used unchanged on diploid organisms
+    pub phenotype: Organism<O>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dominance {
+    // Allele listed earliest in the ranking is expressed, i.e. ranking[0] is dominant over all
+    // others and ranking[3] is recessive to all others
+    Complete([Base; 4]),
+    // Expressed base value is the mean of both alleles' values (as used by
+    // read4_bases_to_unsigned_byte), so decoded numeric fields land between the two homologs
+    Additive,
+}
+
+#[derive(Debug, Clone)]
+pub struct DominanceRules {
+    pub default: Dominance,
+    // Loci whose dominance differs from the default
+    pub per_locus: HashMap<usize, Dominance>,
+}
+
+impl DominanceRules {
+    pub fn uniform(dominance: Dominance) -> Self {
+        DominanceRules {
+            default: dominance,
+            per_locus: HashMap::new(),
+        }
+    }
+
+    pub fn at(&self, locus: usize) -> Dominance {
+        *self.per_locus.get(&locus).unwrap_or(&self.default)
+    }
+}
+
+fn base_from_value(v: u8) -> Base {
+    match v {
+        0 => Base::A,
+        1 => Base::C,
+        2 => Base::T,
+        _ => Base::G,
+    }
+}
+
+fn express_locus(a: Base, b: Base, dominance: Dominance, rng: &mut ThreadRng) -> Base {
+    match dominance {
+        Dominance::Complete(ranking) => {
+            let rank = |x: Base| ranking.iter().position(|r| *r == x).unwrap_or(ranking.len());
+            if rank(a) <= rank(b) {
+                a
+            } else {
+                b
+            }
+        }
+        Dominance::Additive => {
+            let sum = a as u8 + b as u8;
+            // Break ties between neighbouring values at random rather than always rounding down
+            let round_up = sum % 2 == 1 && rng.gen_ratio(1, 2);
+            base_from_value(sum / 2 + round_up as u8)
+        }
+    }
+}
+
+/**
+ * Collapse two homologs into the single sequence passed to an experiment's build function.
+ * Homologs may differ in length after insertions/deletions; loci present on only one
+ * homolog are expressed as-is (hemizygous).
+ */
+pub fn express(homologs: &[BaseSeq; 2], rules: &DominanceRules, rng: &mut ThreadRng) -> BaseSeq {
+    let [h0, h1] = homologs;
+    (0..h0.len().max(h1.len()))
+        .map(|i| match (h0.get(i), h1.get(i)) {
+            (Some(a), Some(b)) => express_locus(*a, *b, rules.at(i), rng),
+            (Some(a), None) | (None, Some(a)) => *a,
+            (None, None) => unreachable!(),
+        })
+        .collect()
+}
+
+pub fn build_diploid<O>(
+    homologs: [BaseSeq; 2],
+    rules: &DominanceRules,
+    build: &dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+    birth_step: i32,
+    rng: &mut ThreadRng,
+) -> DiploidOrganism<O> {
+    let genes = express(&homologs, rules, rng);
+    let body = build(&genes, rng);
+    DiploidOrganism {
+        homologs,
+        phenotype: Organism::new(genes, body, birth_step),
+    }
+}
+
+/**
+ * Produce a single haploid gamete. Starts copying from a random homolog and switches strand
+ * at each locus with probability crossover_prob. If the strand being copied ends first the
+ * gamete ends there too.
+ */
+pub fn meiosis(homologs: &[BaseSeq; 2], crossover_prob: f32, rng: &mut ThreadRng) -> BaseSeq {
+    let mut strand = rng.gen_range(0..2);
+    let mut gamete = BaseSeq::new();
+    for i in 0.. {
+        if i > 0 && rng.gen::<f32>() < crossover_prob {
+            strand = 1 - strand;
+        }
+        match homologs[strand].get(i) {
+            Some(b) => gamete.push(*b),
+            None => break,
+        }
+    }
+    gamete
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SexualReproduction {
+    pub crossover_prob: f32,
+    // Applied independently to each gamete, as in clone_with_mutation
+    pub insertion_prob: f32,
+    pub deletion_prob: f32,
+    pub base_change_prob: f32,
+}
+
+pub fn mate(
+    mother: &[BaseSeq; 2],
+    father: &[BaseSeq; 2],
+    config: &SexualReproduction,
+    rng: &mut ThreadRng,
+) -> [BaseSeq; 2] {
+    let mut gamete = |parent: &[BaseSeq; 2]| {
+        let g = meiosis(parent, config.crossover_prob, rng);
+        clone_with_mutation(
+            &g,
+            rng,
+            config.insertion_prob,
+            config.deletion_prob,
+            config.base_change_prob,
+        )
+    };
+    [gamete(mother), gamete(father)]
+}
+
+/**
+ * Pair surviving organisms at random and produce children_per_pair offspring from each pair.
+ * With an odd population the unpaired organism does not reproduce this round.
+ */
+pub fn reproduce_sexually<O>(
+    parents: &[DiploidOrganism<O>],
+    children_per_pair: usize,
+    config: &SexualReproduction,
+    rules: &DominanceRules,
+    build: &dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+    t: i32,
+    rng: &mut ThreadRng,
+) -> Vec<DiploidOrganism<O>> {
+    let mut order = (0..parents.len()).collect::<Vec<usize>>();
+    order.shuffle(rng);
+
+    let mut children = Vec::new();
+    for pair in order.chunks_exact(2) {
+        let (mother, father) = (&parents[pair[0]].homologs, &parents[pair[1]].homologs);
+        for _ in 0..children_per_pair {
+            let homologs = mate(mother, father, config, rng);
+            if homologs.iter().all(|h| !h.is_empty()) {
+                children.push(build_diploid(homologs, rules, build, t, rng));
+            }
+        }
+    }
+    children
+}
+
+/**
+ * A sexually reproducing population, stepped like Simulation::run_step: organisms die by
+ * their expressed phenotype, fertile survivors mate in random pairs and children fill the
+ * space left up to max_size.
+ */
+pub struct DiploidPopulation<'a, O> {
+    pub organisms: Vec<DiploidOrganism<O>>,
+    pub max_size: usize,
+    pub children_per_pair: usize,
+    pub reproduction: SexualReproduction,
+    pub rules: DominanceRules,
+    pub build: &'a dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+    // Current time step
+    pub t: i32,
+}
+
+impl<'a, O> DiploidPopulation<'a, O> {
+    pub fn step(
+        &mut self,
+        death: &dyn Fn(&Organism<O>, &mut ThreadRng) -> bool,
+        fertile: &dyn Fn(&Organism<O>) -> bool,
+        rng: &mut ThreadRng,
+    ) {
+        let mut survivors = std::mem::take(&mut self.organisms);
+        survivors.retain(|o| !death(&o.phenotype, rng));
+        let (parents, barren): (Vec<_>, Vec<_>) =
+            survivors.into_iter().partition(|o| fertile(&o.phenotype));
+        let children = reproduce_sexually(
+            &parents,
+            self.children_per_pair,
+            &self.reproduction,
+            &self.rules,
+            self.build,
+            self.t,
+            rng,
+        );
+        self.organisms = parents;
+        self.organisms.extend(barren);
+        let space = self.max_size.saturating_sub(self.organisms.len());
+        self.organisms.extend(children.into_iter().take(space));
+        self.t += 1;
+    }
+}
+
+// Fraction of paired loci at which the two homologs differ. Unpaired loci (length
+// differences) count as heterozygous.
+pub fn heterozygosity(homologs: &[BaseSeq; 2]) -> f32 {
+    let [h0, h1] = homologs;
+    let len = h0.len().max(h1.len());
+    if len == 0 {
+        return 0.0;
+    }
+    let paired_diff = h0.iter().zip(h1.iter()).filter(|(a, b)| a != b).count();
+    let unpaired = h0.len().abs_diff(h1.len());
+    (paired_diff + unpaired) as f32 / len as f32
+}
+
+#[derive(Debug, Clone)]
+pub struct HeterozygosityStats {
+    // Mean of per-organism heterozygosity
+    pub observed: f32,
+    // Mean over loci of 1 - sum(p_i^2) using allele frequencies pooled across all homologs
+    pub expected: f32,
+    // 1 - observed / expected, None when the population carries no variation
+    pub fixation_index: Option<f32>,
+}
+
+pub fn heterozygosity_stats<O>(population: &[DiploidOrganism<O>]) -> HeterozygosityStats {
+    if population.is_empty() {
+        return HeterozygosityStats {
+            observed: 0.0,
+            expected: 0.0,
+            fixation_index: None,
+        };
+    }
+
+    let observed = population
+        .iter()
+        .map(|o| heterozygosity(&o.homologs))
+        .sum::<f32>()
+        / population.len() as f32;
+
+    let max_len = population
+        .iter()
+        .flat_map(|o| o.homologs.iter())
+        .map(|h| h.len())
+        .max()
+        .unwrap_or(0);
+    let mut counts = vec![[0usize; 4]; max_len];
+    for h in population.iter().flat_map(|o| o.homologs.iter()) {
+        for (i, b) in h.iter().enumerate() {
+            counts[i][*b as usize] += 1;
+        }
+    }
+    let expected = if max_len == 0 {
+        0.0
+    } else {
+        counts
+            .iter()
+            .map(|c| {
+                let total = c.iter().sum::<usize>() as f32;
+                1.0 - c.iter().map(|n| (*n as f32 / total).powi(2)).sum::<f32>()
+            })
+            .sum::<f32>()
+            / max_len as f32
+    };
+
+    HeterozygosityStats {
+        observed,
+        expected,
+        fixation_index: if expected > 0.0 {
+            Some(1.0 - observed / expected)
+        } else {
+            None
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evol_prim::Base::*;
+
+    #[test]
+    fn dominant_allele_is_expressed() {
+        let mut rng = rand::thread_rng();
+        let homologs = [vec![A, C, T, G], vec![G, T, C, A]];
+        let rules = DominanceRules::uniform(Dominance::Complete([T, A, G, C]));
+        assert_eq!(express(&homologs, &rules, &mut rng), vec![A, T, T, A]);
+
+        // Locus 1 is additive: C (1) and T (2) average to one or the other
+        let mut rules = rules;
+        rules.per_locus.insert(1, Dominance::Additive);
+        rules.per_locus.insert(2, Dominance::Complete([C, A, T, G]));
+        for _ in 0..20 {
+            let expressed = express(&homologs, &rules, &mut rng);
+            assert!(expressed[1] == C || expressed[1] == T);
+            assert_eq!(expressed[2], C);
+        }
+
+        // Hemizygous loci of the longer homolog are expressed as they are
+        let homologs = [vec![C], vec![A, G]];
+        assert_eq!(express(&homologs, &rules, &mut rng), vec![A, G]);
+    }
+
+    #[test]
+    fn meiosis_recombines_homologs() {
+        let mut rng = rand::thread_rng();
+        let homologs = [vec![A; 40], vec![G; 40]];
+        assert!([vec![A; 40], vec![G; 40]].contains(&meiosis(&homologs, 0.0, &mut rng)));
+
+        // Always crossing over alternates strands at every locus
+        let gamete = meiosis(&homologs, 1.0, &mut rng);
+        assert_eq!(gamete.len(), 40);
+        assert!(gamete.windows(2).all(|w| w[0] != w[1]));
+
+        let gamete = meiosis(&homologs, 0.5, &mut rng);
+        assert!(gamete.contains(&A) && gamete.contains(&G));
+    }
+
+    #[test]
+    fn population_keeps_to_its_size() {
+        let mut rng = rand::thread_rng();
+        let build = |g: &BaseSeq, _: &mut ThreadRng| g.len();
+        let rules = DominanceRules::uniform(Dominance::Additive);
+        let organisms = (0..10)
+            .map(|_| build_diploid([vec![A; 8], vec![G; 8]], &rules, &build, 0, &mut rng))
+            .collect();
+        let mut population = DiploidPopulation {
+            organisms,
+            max_size: 30,
+            children_per_pair: 4,
+            reproduction: SexualReproduction {
+                crossover_prob: 0.2,
+                insertion_prob: 0.0,
+                deletion_prob: 0.0,
+                base_change_prob: 0.0,
+            },
+            rules,
+            build: &build,
+            t: 0,
+        };
+        population.step(&|_, _| false, &|_| true, &mut rng);
+        assert_eq!(population.organisms.len(), 30);
+        assert_eq!(population.t, 1);
+        // Children carry one gamete from each heterozygous parent
+        assert!(population.organisms[10..]
+            .iter()
+            .all(|o| o.homologs.iter().all(|h| h.len() == 8)));
+        population.step(&|_, _| true, &|_| true, &mut rng);
+        assert!(population.organisms.is_empty());
+    }
+}
@@ -0,0 +1,376 @@
+// Genetic distances between sequences and population diversity statistics
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::evol_prim::*;
+
+// Mismatches over the overlapping prefix, plus one per base of length difference
+pub fn hamming(a: &[Base], b: &[Base]) -> usize {
+    a.iter().zip(b.iter()).filter(|(x, y)| x != y).count() + a.len().abs_diff(b.len())
+}
+
+// Minimum number of single base insertions, deletions and substitutions turning a into b
+pub fn levenshtein(a: &[Base], b: &[Base]) -> usize {
+    let mut prev = (0..=b.len()).collect::<Vec<usize>>();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, x) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = prev[j] + (x != y) as usize;
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct AlignmentScoring {
+    pub matched: i32,
+    pub mismatched: i32,
+    pub gap: i32,
+}
+
+impl Default for AlignmentScoring {
+    fn default() -> Self {
+        AlignmentScoring {
+            matched: 1,
+            mismatched: -1,
+            gap: -2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct AlignmentCell {
+    score: i32,
+    matches: usize,
+    length: usize,
+}
+
+/**
+ * Global (Needleman-Wunsch) alignment of a and b.
+ * Returns 1 - identity of the optimal alignment, where identity is matched columns over
+ * alignment length, so unrelated sequences approach 1 and identical sequences give 0.
+ */
+pub fn alignment_distance(a: &[Base], b: &[Base], scoring: &AlignmentScoring) -> f32 {
+    let gap = |c: AlignmentCell| AlignmentCell {
+        score: c.score + scoring.gap,
+        matches: c.matches,
+        length: c.length + 1,
+    };
+    let best = |x: AlignmentCell, y: AlignmentCell| {
+        if (y.score, y.matches) > (x.score, x.matches) {
+            y
+        } else {
+            x
+        }
+    };
+
+    let mut prev = Vec::with_capacity(b.len() + 1);
+    prev.push(AlignmentCell::default());
+    for j in 0..b.len() {
+        prev.push(gap(prev[j]));
+    }
+    let mut cur = vec![AlignmentCell::default(); b.len() + 1];
+    for x in a {
+        cur[0] = gap(prev[0]);
+        for (j, y) in b.iter().enumerate() {
+            let diag = AlignmentCell {
+                score: prev[j].score
+                    + if x == y {
+                        scoring.matched
+                    } else {
+                        scoring.mismatched
+                    },
+                matches: prev[j].matches + (x == y) as usize,
+                length: prev[j].length + 1,
+            };
+            cur[j + 1] = best(best(diag, gap(prev[j + 1])), gap(cur[j]));
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    let end = prev[b.len()];
+    if end.length == 0 {
+        0.0
+    } else {
+        1.0 - end.matches as f32 / end.length as f32
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LocusFrequencies {
+    // Number of sequences long enough to have this locus
+    pub coverage: usize,
+    // Indexed by `Base as usize`
+    pub frequencies: [f32; 4],
+}
+
+impl LocusFrequencies {
+    // Shannon entropy in bits, 0 for a fixed locus and 2 when all four bases are equally common
+    pub fn entropy(&self) -> f32 {
+        self.frequencies
+            .iter()
+            .filter(|p| **p > 0.0)
+            .map(|p| -p * p.log2())
+            .sum()
+    }
+}
+
+fn base_counts<'a>(seqs: impl Iterator<Item = &'a BaseSeq>) -> Vec<[usize; 4]> {
+    let mut counts: Vec<[usize; 4]> = Vec::new();
+    for s in seqs {
+        if counts.len() < s.len() {
+            counts.resize(s.len(), [0; 4]);
+        }
+        for (i, b) in s.iter().enumerate() {
+            counts[i][*b as usize] += 1;
+        }
+    }
+    counts
+}
+
+pub fn allele_frequencies<'a>(seqs: impl Iterator<Item = &'a BaseSeq>) -> Vec<LocusFrequencies> {
+    base_counts(seqs)
+        .into_iter()
+        .map(|c| {
+            let coverage = c.iter().sum::<usize>();
+            LocusFrequencies {
+                coverage,
+                frequencies: c.map(|n| n as f32 / coverage as f32),
+            }
+        })
+        .collect()
+}
+
+/**
+ * Exact mean Hamming distance over all pairs, computed from per-locus base counts in
+ * O(population * length) rather than comparing every pair.
+ */
+pub fn mean_pairwise_hamming(seqs: &[&BaseSeq]) -> f32 {
+    let n = seqs.len();
+    if n < 2 {
+        return 0.0;
+    }
+    let differing_pairs = base_counts(seqs.iter().copied())
+        .iter()
+        .map(|c| {
+            let covered = c.iter().sum::<usize>();
+            let same = c.iter().map(|k| k * k).sum::<usize>();
+            // Pairs covering the locus with different bases, plus pairs where only one does
+            (covered * covered - same) / 2 + covered * (n - covered)
+        })
+        .sum::<usize>();
+    differing_pairs as f32 / (n * (n - 1) / 2) as f32
+}
+
+// Mean Levenshtein distance over `samples` randomly chosen pairs, since the exact value is
+// quadratic in both population size and genome length
+pub fn sampled_mean_pairwise_levenshtein(
+    seqs: &[&BaseSeq],
+    samples: usize,
+    rng: &mut ThreadRng,
+) -> f32 {
+    if seqs.len() < 2 || samples == 0 {
+        return 0.0;
+    }
+    (0..samples)
+        .map(|_| {
+            let i = rng.gen_range(0..seqs.len());
+            let j = (i + rng.gen_range(1..seqs.len())) % seqs.len();
+            levenshtein(seqs[i], seqs[j])
+        })
+        .sum::<usize>() as f32
+        / samples as f32
+}
+
+pub fn unique_genotypes(seqs: &[&BaseSeq]) -> usize {
+    seqs.iter().collect::<HashSet<_>>().len()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LengthDistribution {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f32,
+    pub stdev: f32,
+    // Genome length -> number of organisms
+    pub histogram: BTreeMap<usize, usize>,
+}
+
+pub fn length_distribution(seqs: &[&BaseSeq]) -> LengthDistribution {
+    if seqs.is_empty() {
+        return LengthDistribution::default();
+    }
+    let mut histogram = BTreeMap::new();
+    for s in seqs {
+        *histogram.entry(s.len()).or_insert(0) += 1;
+    }
+    let mean = seqs.iter().map(|s| s.len()).sum::<usize>() as f32 / seqs.len() as f32;
+    let variance = seqs
+        .iter()
+        .map(|s| (s.len() as f32 - mean).powi(2))
+        .sum::<f32>()
+        / seqs.len() as f32;
+    LengthDistribution {
+        min: *histogram.keys().next().unwrap(),
+        max: *histogram.keys().next_back().unwrap(),
+        mean,
+        stdev: variance.sqrt(),
+        histogram,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiversityStats {
+    pub population: usize,
+    pub unique_genotypes: usize,
+    pub mean_pairwise_hamming: f32,
+    pub sampled_mean_pairwise_levenshtein: f32,
+    // Mean per-locus Shannon entropy (bits)
+    pub mean_entropy: f32,
+    pub loci: Vec<LocusFrequencies>,
+    pub lengths: LengthDistribution,
+}
+
+// Number of random pairs used for the Levenshtein estimate in DiversityStats
+const LEVENSHTEIN_SAMPLES: usize = 200;
+
+impl DiversityStats {
+    pub fn compute<O>(organisms: &[Organism<O>], rng: &mut ThreadRng) -> Self {
+        let seqs = organisms.iter().map(|o| &o.genes).collect::<Vec<&BaseSeq>>();
+        let loci = allele_frequencies(seqs.iter().copied());
+        let mean_entropy = if loci.is_empty() {
+            0.0
+        } else {
+            loci.iter().map(|l| l.entropy()).sum::<f32>() / loci.len() as f32
+        };
+
+        DiversityStats {
+            population: seqs.len(),
+            unique_genotypes: unique_genotypes(&seqs),
+            mean_pairwise_hamming: mean_pairwise_hamming(&seqs),
+            sampled_mean_pairwise_levenshtein: sampled_mean_pairwise_levenshtein(
+                &seqs,
+                LEVENSHTEIN_SAMPLES,
+                rng,
+            ),
+            mean_entropy,
+            loci,
+            lengths: length_distribution(&seqs),
+        }
+    }
+}
+
+impl fmt::Display for DiversityStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unique genotypes {}/{}, mean hamming {:.3}, mean levenshtein {:.3}, mean locus entropy {:.3}, length [{}, {}] mean {:.2} stdev {:.2}",
+            self.unique_genotypes,
+            self.population,
+            self.mean_pairwise_hamming,
+            self.sampled_mean_pairwise_levenshtein,
+            self.mean_entropy,
+            self.lengths.min,
+            self.lengths.max,
+            self.lengths.mean,
+            self.lengths.stdev
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seq(s: &str) -> BaseSeq {
+        parse_seq(s).unwrap()
+    }
+
+    #[test]
+    fn distances_of_hand_worked_pairs() {
+        assert_eq!(hamming(&seq("ACTG"), &seq("ACTG")), 0);
+        // One mismatch in the overlap, one base longer
+        assert_eq!(hamming(&seq("ACTG"), &seq("AGT")), 2);
+        assert_eq!(hamming(&seq(""), &seq("ACG")), 3);
+
+        assert_eq!(levenshtein(&seq("ACTG"), &seq("AGT")), 2);
+        // A shift costs two edits but mismatches every locus
+        assert_eq!(levenshtein(&seq("ACAC"), &seq("CACA")), 2);
+        assert_eq!(hamming(&seq("ACAC"), &seq("CACA")), 4);
+        assert_eq!(levenshtein(&seq("AC"), &seq("")), 2);
+
+        let scoring = AlignmentScoring::default();
+        assert_eq!(
+            alignment_distance(&seq("ACTG"), &seq("ACTG"), &scoring),
+            0.0
+        );
+        // AC-G against ACTG: 3 matches over 4 columns
+        assert_eq!(
+            alignment_distance(&seq("ACTG"), &seq("ACG"), &scoring),
+            0.25
+        );
+        assert_eq!(alignment_distance(&seq(""), &seq("AC"), &scoring), 1.0);
+        assert_eq!(alignment_distance(&seq(""), &seq(""), &scoring), 0.0);
+    }
+
+    #[test]
+    fn alignment_ties_go_to_more_matches() {
+        // Every alignment scores 0, so A C - against - C A (one match) beats AC against CA
+        let flat = AlignmentScoring {
+            matched: 0,
+            mismatched: 0,
+            gap: 0,
+        };
+        let d = alignment_distance(&seq("AC"), &seq("CA"), &flat);
+        assert!((d - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mean_pairwise_hamming_matches_every_pair() {
+        let population = ["ACTG", "AC", "ACTGA", "G", "ACTG", "", "TTTTTT"].map(seq);
+        let seqs = population.iter().collect::<Vec<&BaseSeq>>();
+        let mut total = 0;
+        let mut pairs = 0;
+        for i in 0..seqs.len() {
+            for j in i + 1..seqs.len() {
+                total += hamming(seqs[i], seqs[j]);
+                pairs += 1;
+            }
+        }
+        let brute_force = total as f32 / pairs as f32;
+        assert!((mean_pairwise_hamming(&seqs) - brute_force).abs() < 1e-6);
+        assert_eq!(mean_pairwise_hamming(&seqs[..1]), 0.0);
+    }
+
+    #[test]
+    fn allele_frequencies_and_lengths() {
+        let population = ["AC", "AG", "A"].map(seq);
+        let seqs = population.iter().collect::<Vec<&BaseSeq>>();
+        let loci = allele_frequencies(seqs.iter().copied());
+        assert_eq!(loci.len(), 2);
+        assert_eq!(
+            (loci[0].coverage, loci[0].frequencies),
+            (3, [1.0, 0.0, 0.0, 0.0])
+        );
+        assert_eq!(loci[0].entropy(), 0.0);
+        assert_eq!(
+            (loci[1].coverage, loci[1].frequencies),
+            (2, [0.0, 0.5, 0.0, 0.5])
+        );
+        assert_eq!(loci[1].entropy(), 1.0);
+
+        let lengths = length_distribution(&seqs);
+        assert_eq!((lengths.min, lengths.max), (1, 2));
+        assert_eq!(lengths.histogram, BTreeMap::from([(1, 1), (2, 2)]));
+        assert!((lengths.mean - 5.0 / 3.0).abs() < 1e-6);
+        assert!((lengths.stdev - (2.0f32 / 9.0).sqrt()).abs() < 1e-6);
+        assert_eq!(unique_genotypes(&seqs), 3);
+    }
+}
@@ -3,22 +3,119 @@
 use rand::Rng;
 use rand::prelude::ThreadRng;
 
+use crate::e1;
 use crate::evol_prim::*;
 use crate::evol_prim::BaseSeq;
-use crate::evol_prim::Base::*;
+use crate::sim::Simulation;
+use crate::soup::Soup;
 
-const E1_REPRODUCE_PREFIX: &'static [Base] = &[A, T, A, T];
+pub use crate::e1::E1_REPRODUCE_PREFIX;
 
-pub fn reproduce(s: &BaseSeq, rng: &mut ThreadRng) -> Vec<BaseSeq> {
-    if s.starts_with(&E1_REPRODUCE_PREFIX) {
-        panic!() // Stop simulation when true reproduction starts
-    } else if rng.gen_ratio(1, 2) {
-        return vec![clone_with_mutation(s, rng, 0.1, 0.1, 0.33), clone_with_mutation(s, rng, 0.1, 0.1, 0.33)]
-        // TODO: For E0 (primordial soup), we should really just be injecting random sequences into the primordial soup, not cloning existing sequences with modification
-    }
+// Nothing in the soup reproduces. New sequences only arrive through the soup's influx of
+// random sequences (see soup()), and the engine reports when E1_REPRODUCE_PREFIX appears.
+pub fn reproduce(_: &Organism<()>, _: &(), _: &mut ThreadRng) -> Vec<BaseSeq> {
     Vec::new()
 }
 
-pub fn death(s: &BaseSeq, rng: &mut ThreadRng) -> bool {
-    s.len() == 0 || rng.gen::<f32>() < 0.5
-}
\ No newline at end of file
+pub fn death(org: &Organism<()>, _: &(), rng: &mut ThreadRng) -> bool {
+    org.genes.is_empty() || rng.gen::<f32>() < 0.5
+}
+
+pub fn build(_: &BaseSeq, _: &mut ThreadRng) {}
+
+pub fn no_update(_: &mut Organism<()>, _: &(), _: &mut ThreadRng) {}
+
+pub fn soup() -> Soup {
+    Soup {
+        influx: 50,
+        min_len: 1,
+        max_len: 8,
+        replicator: E1_REPRODUCE_PREFIX,
+        hand_off: true,
+        detection: None,
+    }
+}
+
+fn e1_reproduce(org: &Organism<()>, _: &(), rng: &mut ThreadRng) -> Vec<BaseSeq> {
+    e1::reproduce(&org.genes, rng)
+}
+
+fn e1_death(org: &Organism<()>, _: &(), rng: &mut ThreadRng) -> bool {
+    e1::death(&org.genes, rng)
+}
+
+// Continue from the soup's current population as an E1 (prefix replication) run
+pub fn hand_off_to_e1<'a>(soup_sim: &Simulation<'a, (), ()>, max_t: i32) -> Simulation<'a, (), ()> {
+    Simulation {
+        R: &e1_reproduce,
+        D: &e1_death,
+        B: &build,
+        U: &no_update,
+        L: &no_update,
+        organisms: soup_sim.organisms.clone(),
+        environment: (),
+        max_sequences: soup_sim.max_sequences,
+        t: soup_sim.t,
+        max_t,
+        rng: soup_sim.rng.clone(),
+        speciation: None,
+        hgt: None,
+        tracker: None,
+        elitism: None,
+        hall_of_fame: None,
+        soup: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An E0 run whose organisms never die, seeded with a replicator
+    fn seeded(hand_off: bool) -> Simulation<'static, (), ()> {
+        Simulation {
+            R: &reproduce,
+            D: &|_: &Organism<()>, _: &(), _: &mut ThreadRng| false,
+            B: &build,
+            U: &no_update,
+            L: &no_update,
+            organisms: vec![Organism::new(E1_REPRODUCE_PREFIX.to_vec(), (), 0)],
+            environment: (),
+            max_sequences: 100,
+            t: 0,
+            max_t: 5,
+            rng: rand::thread_rng(),
+            speciation: None,
+            hgt: None,
+            tracker: None,
+            elitism: None,
+            hall_of_fame: None,
+            soup: Some(Soup { hand_off, ..soup() }),
+        }
+    }
+
+    #[test]
+    fn run_stops_at_the_first_replicator_when_handing_off() {
+        let mut sim = seeded(true);
+        let seed = sim.organisms[0].id;
+        sim.run(None).unwrap();
+        assert_eq!(sim.t, 1);
+        let detection = sim.soup.as_ref().unwrap().detection.as_ref().unwrap();
+        assert_eq!((detection.t, detection.id), (0, seed));
+
+        let mut sim = seeded(false);
+        sim.run(None).unwrap();
+        assert_eq!(sim.t, 5);
+    }
+
+    #[test]
+    fn hand_off_keeps_the_population() {
+        let mut sim = seeded(true);
+        sim.run(None).unwrap();
+        let e1 = hand_off_to_e1(&sim, 50);
+        let ids = |s: &Simulation<(), ()>| s.organisms.iter().map(|o| o.id).collect::<Vec<u64>>();
+        assert_eq!(ids(&e1), ids(&sim));
+        assert_eq!((e1.t, e1.max_t, e1.max_sequences), (1, 50, 100));
+        assert!(e1.soup.is_none());
+    }
+}
@@ -3,6 +3,7 @@
 use rand::Rng;
 use rand::prelude::ThreadRng;
 
+use crate::diploid::*;
 use crate::evol_prim::*;
 use crate::evol_prim::BaseSeq;
 use crate::evol_prim::Base::*;
@@ -18,4 +19,46 @@ pub fn reproduce(s: &BaseSeq, rng: &mut ThreadRng) -> Vec<BaseSeq> {
 
 pub fn death(s: &BaseSeq, rng: &mut ThreadRng) -> bool {
     s.len() == 0 || rng.gen::<f32>() < 0.5
-}
\ No newline at end of file
+}
+
+// E1 with diploid organisms mating in pairs. An organism is fertile if its expressed genome
+// starts with the prefix; A and T are dominant over C and G, so carriers of the prefix on
+// one homolog mate too.
+pub fn diploid_rules() -> DominanceRules {
+    DominanceRules::uniform(Dominance::Complete([A, T, C, G]))
+}
+
+pub fn diploid_population<'a>(ancestors: &[[BaseSeq; 2]], rng: &mut ThreadRng) -> DiploidPopulation<'a, ()> {
+    let rules = diploid_rules();
+    let organisms = ancestors
+        .iter()
+        .map(|h| build_diploid(h.clone(), &rules, &|_, _| (), 0, rng))
+        .collect();
+    DiploidPopulation {
+        organisms,
+        max_size: 400,
+        // Two children per parent, as reproduce gives
+        children_per_pair: 4,
+        reproduction: SexualReproduction {
+            crossover_prob: 0.05,
+            insertion_prob: 0.01,
+            deletion_prob: 0.01,
+            base_change_prob: 0.05,
+        },
+        rules,
+        build: &|_, _| (),
+        t: 0,
+    }
+}
+
+// Run the diploid population for `steps` steps, returning its heterozygosity after each
+pub fn run_diploid(population: &mut DiploidPopulation<()>, steps: usize, rng: &mut ThreadRng) -> Vec<HeterozygosityStats> {
+    let dies = |o: &Organism<()>, rng: &mut ThreadRng| death(&o.genes, rng);
+    let fertile = |o: &Organism<()>| o.genes.starts_with(E1_REPRODUCE_PREFIX);
+    (0..steps)
+        .map(|_| {
+            population.step(&dies, &fertile, rng);
+            heterozygosity_stats(&population.organisms)
+        })
+        .collect()
+}
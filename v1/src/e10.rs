@@ -1,7 +1,10 @@
 use rand::prelude::ThreadRng;
 use rand::Rng;
 
+use crate::evol_prim::Base::*;
 use crate::evol_prim::*;
+use crate::genes::{clone_with_duplication, express, Dosage, GeneSchema};
+use crate::mutscan::Trait;
 
 #[derive(Debug, Clone)]
 pub struct Body10 {
@@ -9,9 +12,20 @@ pub struct Body10 {
     // First component is selected, second is learned
     pub stimulus_response_vector: [f32; 2], // [-1,1]
     pub learning_factor: f32,               // [0,1]
+    // Weights of the learning rule's inputs, see learn
+    pub learning_rule: [f32; LEARNING_RULE_INPUTS],
+    // The learning rule's memory of its last step: the reward for where the organism ended
+    // up, the reward it got in the same environment before making its last change, and that
+    // change
+    pub last_reward: f32,
+    pub reward_before_change: f32,
+    pub last_change: f32,
     pub track: bool,
 }
 
+// reward, reward change, last change, reward change x direction of last change
+pub const LEARNING_RULE_INPUTS: usize = 4;
+
 #[derive(Debug, Clone)]
 pub struct Environment10 {
     pub safe_zone_low: f32,  // [-1,1]
@@ -42,6 +56,17 @@ pub fn reproduce(org: &Organism<Body10>, env: &Environment10, rng: &mut ThreadRn
     }
 }
 
+// Closeness of the organism to the middle of the safe zone, in [-1, 0]
+pub fn score(org: &Organism<Body10>, env: &Environment10) -> f32 {
+    reward(org.body.position, env)
+}
+
+// The reward an organism perceives at position, as score computes it
+fn reward(position: f32, env: &Environment10) -> f32 {
+    let centre = env.safe_zone_low + ((env.safe_zone_high - env.safe_zone_low) / 2.0);
+    -wrapping_dist(position, centre).abs()
+}
+
 fn stimulus_response_circuit(
     org: &Organism<Body10>,
     env: &Environment10,
@@ -83,6 +108,8 @@ pub fn update(org: &mut Organism<Body10>, env: &Environment10, rng: &mut ThreadR
     // Move in response to being in danger zone.
 
     org.body.position = stimulus_response_circuit(org, env, rng);
+    // What the organism perceives of where it ended up, for its next learn step
+    org.body.last_reward = reward(org.body.position, env);
 }
 
 fn stimulus_reception(stimulus: f32) -> f32 {
@@ -101,29 +128,51 @@ fn stimulus_response(
 }
 
 pub fn learn(org: &mut Organism<Body10>, env: &Environment10, rng: &mut ThreadRng) {
-    // Will be its own neural net or some other logic under selection
-    // For now, test with a perfectly accurate hardcoded solution
-    // TODO: learn function should not have direct access to the current values
-    // of the sim response circuit
-
     let debug = rng.gen::<f32>() < 0.001;
+    learn_step(org, env, debug, rng);
+}
 
-    let target_pos = env.safe_zone_low + ((env.safe_zone_high - env.safe_zone_low) / 2.0);
-    let current_learned_pos = stimulus_response_circuit(org, env, rng);
-    let loss = target_pos - current_learned_pos;
+/**
+ * The learning rule sees only the organism's own perceptions of positions it actually took.
+ * In each environment the organism first responds as it did before, learns, then responds
+ * again (update); the reward for where it ended up, and how much better that was than its
+ * first response in the same environment, are what its last change earned. The change is
+ * learning_factor times the weighted sum of
+ *   reward, reward change, last change, reward change * sign(last change)
+ * where the last input lets a rule keep moving the way that paid off (hill climbing).
+ */
+fn learn_step(org: &mut Organism<Body10>, env: &Environment10, debug: bool, rng: &mut ThreadRng) {
+    let first_response = stimulus_response_circuit(org, env, rng);
+    let first_reward = reward(first_response, env);
+    let body = &mut org.body;
+    body.position = first_response;
+    let reward = body.last_reward;
+    let reward_change = body.last_reward - body.reward_before_change;
+    let inputs = [
+        reward,
+        reward_change,
+        body.last_change,
+        reward_change * body.last_change.signum(),
+    ];
+    let change = body.learning_factor
+        * body
+            .learning_rule
+            .iter()
+            .zip(inputs)
+            .map(|(w, x)| w * x)
+            .sum::<f32>();
 
     if debug {
         println!(
-            "target_pos: {}, current_learned_pos: {}, loss: {}, learning_factor: {}, updated_srv1: {}, updated_srv1_if_perfect_lf: {}",
-            target_pos, current_learned_pos, loss, org.body.learning_factor, org.body.stimulus_response_vector[1] + loss * org.body.learning_factor, org.body.stimulus_response_vector[1] + loss * 0.3333
+            "first_response: {}, reward: {}, reward_change: {}, learning_factor: {}, learning_rule: {:?}, change: {}",
+            first_response, reward, reward_change, body.learning_factor, body.learning_rule, change
         );
     }
 
-    // IP
-    org.body.stimulus_response_vector[1] += loss * org.body.learning_factor;
-
-    // org.body.stimulus_response_vector[1] =
-    //     (1.0 - (3.0 * org.body.stimulus_response_vector[0])) / 3.0
+    let learned = body.stimulus_response_vector[1] + change;
+    body.stimulus_response_vector[1] = learned.clamp(-1.0, 1.0);
+    body.reward_before_change = first_reward;
+    body.last_change = change;
 }
 
 /**
@@ -131,6 +180,8 @@ pub fn learn(org: &mut Organism<Body10>, env: &Environment10, rng: &mut ThreadRn
  * Take the first 4 bases as a one byte unsigned int.
  * Treat as little endian, missing bases treated as 0.
  * Finally, subtract 128 divide by 2**7 to cast into the range [-1, 1]
+ * The next LEARNING_RULE_INPUTS bytes are the learning rule's weights; missing ones are 0, so
+ * genomes of just two bytes do not learn.
  */
 pub fn build(seq: &BaseSeq, _: &mut ThreadRng) -> Body10 {
     let mut si = seq.iter().peekable();
@@ -145,6 +196,13 @@ pub fn build(seq: &BaseSeq, _: &mut ThreadRng) -> Body10 {
         response2_raw = read4_bases_to_unsigned_byte(&mut si);
     }
 
+    let mut learning_rule = [0.0; LEARNING_RULE_INPUTS];
+    for w in learning_rule.iter_mut() {
+        if si.peek().is_some() {
+            *w = byte_to_feature_space(read4_bases_to_unsigned_byte(&mut si));
+        }
+    }
+
     // Cast into [-1, 1]
     Body10 {
         position: 0.0,
@@ -152,6 +210,266 @@ pub fn build(seq: &BaseSeq, _: &mut ThreadRng) -> Body10 {
         // stimulus_response_vector: [0.0, 0.0],
         learning_factor: byte_to_feature_space(response2_raw),
         // learning_factor: 0.0,
+        learning_rule,
+        last_reward: 0.0,
+        reward_before_change: 0.0,
+        last_change: 0.0,
+        track: false,
+    }
+}
+
+// Whether a genome read by build learns at all, i.e. has a positive learning factor
+pub fn learns(seq: &BaseSeq) -> bool {
+    byte_to_feature_space(read4_bases_to_unsigned_byte(&mut seq.iter().skip(4))) > 0.0
+}
+
+// Random environment history of `steps` steps, as Simulation::run_step would produce
+pub fn random_environments(steps: usize, rng: &mut ThreadRng) -> Vec<Environment10> {
+    let mut env = Environment10 {
+        safe_zone_low: 0.0,
+        safe_zone_high: 0.2,
+    };
+    (0..steps)
+        .map(|_| {
+            env.update(rng);
+            env.clone()
+        })
+        .collect()
+}
+
+/**
+ * Expected number of children over a lifetime lived through `envs`, one environment per
+ * step. Follows Simulation::run_step: each step the organism learns and moves, then it has
+ * one child if in the safe zone and otherwise dies with probability 0.4 (the rare
+ * background death is ignored).
+ */
+pub fn evaluate(body: &Body10, envs: &[Environment10], rng: &mut ThreadRng) -> f32 {
+    let mut org = Organism::new(Vec::new(), body.clone(), 0);
+    let mut alive = 1.0;
+    let mut children = 0.0;
+    for env in envs {
+        learn_step(&mut org, env, false, rng);
+        update(&mut org, env, rng);
+        if in_zone_possibly_wrapped(org.body.position, env.safe_zone_low, env.safe_zone_high) {
+            children += alive;
+        } else {
+            alive *= 0.6;
+        }
+    }
+    children
+}
+
+// `samples` random environment histories of `steps` steps, to score bodies against
+pub fn random_histories(
+    steps: usize,
+    samples: usize,
+    rng: &mut ThreadRng,
+) -> Vec<Vec<Environment10>> {
+    (0..samples)
+        .map(|_| random_environments(steps, rng))
+        .collect()
+}
+
+/**
+ * evaluate averaged over the given environment histories. Scoring every body against the
+ * same histories (see random_histories) makes equal bodies score equally and differences
+ * between bodies more than sampling noise.
+ */
+pub fn expected_fitness(
+    body: &Body10,
+    histories: &[Vec<Environment10>],
+    rng: &mut ThreadRng,
+) -> f32 {
+    let total = histories
+        .iter()
+        .map(|envs| evaluate(body, envs, rng))
+        .sum::<f32>();
+    total / histories.len().max(1) as f32
+}
+
+// Genotypes building the same Body10 share a key
+pub fn phenotype_key(body: &Body10) -> String {
+    format!(
+        "srv0={} lf={} rule={:?}",
+        body.stimulus_response_vector[0], body.learning_factor, body.learning_rule
+    )
+}
+
+// Body traits set directly by the genome, for mutscan::knockout_importance
+pub fn traits() -> Vec<Trait<'static, Body10>> {
+    vec![
+        ("srv0", &|b: &Body10| b.stimulus_response_vector[0]),
+        ("learning_factor", &|b: &Body10| b.learning_factor),
+        ("rule_reward", &|b: &Body10| b.learning_rule[0]),
+        ("rule_reward_change", &|b: &Body10| b.learning_rule[1]),
+        ("rule_last_change", &|b: &Body10| b.learning_rule[2]),
+        ("rule_hill_climb", &|b: &Body10| b.learning_rule[3]),
+    ]
+}
+
+// Gene-based genome: TATA, a tag (A: srv0, C: learning factor, T: learning rule term), 4
+// payload bases read as build reads them, then GGG. Duplicated srv0 and learning factor genes
+// are combined by GENE_DOSAGE; the functional learning rule terms set the rule's weights in
+// genome order, so duplicating one lengthens the rule.
+pub const GENE_SCHEMA: GeneSchema = GeneSchema {
+    start: &[T, A, T, A],
+    stop: &[G, G, G],
+    payload_len: 4,
+    kinds: 3,
+};
+pub const GENE_DOSAGE: [Dosage; 2] = [Dosage::Sum, Dosage::FirstFunctional];
+const LEARNING_RULE_GENE: usize = 2;
+pub const GENE_DUPLICATION_PROB: f32 = 0.02;
+
+fn gene_value(payload: &[Base]) -> f32 {
+    byte_to_feature_space(read4_bases_to_unsigned_byte(&mut payload.iter()))
+}
+
+// Like build, but from GENE_SCHEMA genes. A gene without a functional copy reads as 0.
+pub fn build_genes(seq: &BaseSeq, _: &mut ThreadRng) -> Body10 {
+    let copies = GENE_SCHEMA.decode(seq);
+    let trait_value = |kind: usize| {
+        express(&copies, kind, GENE_DOSAGE[kind], &gene_value)
+            .unwrap_or(0.0)
+            .clamp(-1.0, 1.0)
+    };
+    let mut learning_rule = [0.0; LEARNING_RULE_INPUTS];
+    let terms = copies
+        .iter()
+        .filter(|c| c.kind == LEARNING_RULE_GENE && c.functional);
+    for (w, term) in learning_rule.iter_mut().zip(terms) {
+        *w = gene_value(&term.payload);
+    }
+    Body10 {
+        position: 0.0,
+        stimulus_response_vector: [trait_value(0), 0.0],
+        learning_factor: trait_value(1),
+        learning_rule,
+        last_reward: 0.0,
+        reward_before_change: 0.0,
+        last_change: 0.0,
         track: false,
     }
 }
+
+// One copy of each gene, encoding the given srv0 and learning factor payloads, then one
+// learning rule term per payload in learning_rule
+pub fn gene_ancestor(
+    srv0: &[Base],
+    learning_factor: &[Base],
+    learning_rule: &[&[Base]],
+) -> BaseSeq {
+    let mut seq = [
+        GENE_SCHEMA.encode(0, srv0),
+        GENE_SCHEMA.encode(1, learning_factor),
+    ]
+    .concat();
+    for term in learning_rule {
+        seq.extend(GENE_SCHEMA.encode(LEARNING_RULE_GENE, term));
+    }
+    seq
+}
+
+// reproduce for gene-based genomes, which may also duplicate a segment of up to two genes
+pub fn reproduce_genes(
+    org: &Organism<Body10>,
+    env: &Environment10,
+    rng: &mut ThreadRng,
+) -> Vec<BaseSeq> {
+    if in_zone_possibly_wrapped(org.body.position, env.safe_zone_low, env.safe_zone_high) {
+        let max_segment =
+            2 * (GENE_SCHEMA.start.len() + 1 + GENE_SCHEMA.payload_len + GENE_SCHEMA.stop.len());
+        vec![clone_with_duplication(
+            &org.genes,
+            rng,
+            GENE_DUPLICATION_PROB,
+            max_segment,
+            0.005,
+            0.005,
+            0.02,
+        )]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genes::duplicate_segment;
+
+    fn env(low: f32) -> Environment10 {
+        Environment10 {
+            safe_zone_low: low,
+            safe_zone_high: low + 0.2,
+        }
+    }
+
+    #[test]
+    fn learning_rule_sees_outcomes_from_one_environment() {
+        let mut rng = rand::thread_rng();
+        let mut body = build(&vec![A; 8], &mut rng);
+        body.stimulus_response_vector[0] = 0.2;
+        body.learning_factor = 0.5;
+        body.learning_rule = [-1.0, 0.0, 0.0, 0.0];
+        let mut org = Organism::new(Vec::new(), body, 0);
+
+        // The first learn step has no outcome to learn from
+        let (a, b) = (env(0.5), env(0.1));
+        learn_step(&mut org, &a, false, &mut rng);
+        assert_eq!(org.body.last_change, 0.0);
+        let first = org.body.position;
+        update(&mut org, &a, &mut rng);
+        assert_eq!(org.body.reward_before_change, reward(first, &a));
+        assert_eq!(org.body.last_reward, reward(org.body.position, &a));
+
+        // In a new environment the rule still learns from how it fared in the last one
+        let expected = 0.5 * -org.body.last_reward;
+        learn_step(&mut org, &b, false, &mut rng);
+        assert!((org.body.last_change - expected).abs() < 1e-6);
+        assert_eq!(org.body.reward_before_change, reward(org.body.position, &b));
+    }
+
+    fn payload(feature: f32) -> [Base; 4] {
+        unsigned_byte_to_4_bases(feature_space_to_byte(feature))
+    }
+
+    #[test]
+    fn duplicated_genes_build_as_documented() {
+        let mut rng = rand::thread_rng();
+        let seq = gene_ancestor(
+            &payload(0.25),
+            &payload(0.5),
+            &[&payload(0.25), &payload(-0.5)],
+        );
+        let gene_len =
+            GENE_SCHEMA.start.len() + 1 + GENE_SCHEMA.payload_len + GENE_SCHEMA.stop.len();
+
+        // srv0 copies add up, only the first learning factor copy counts, and each learning
+        // rule copy is one more term
+        let mut duplicated = [
+            seq[..2 * gene_len].to_vec(),
+            GENE_SCHEMA.encode(0, &payload(0.5)),
+            GENE_SCHEMA.encode(1, &payload(-0.75)),
+            seq[2 * gene_len..].to_vec(),
+        ]
+        .concat();
+        duplicated = duplicate_segment(&duplicated, 4 * gene_len, gene_len);
+        let body = build_genes(&duplicated, &mut rng);
+        assert_eq!(body.stimulus_response_vector, [0.75, 0.0]);
+        assert_eq!(body.learning_factor, 0.5);
+        assert_eq!(body.learning_rule, [0.25, 0.25, -0.5, 0.0]);
+
+        // Sums are clamped, and terms past LEARNING_RULE_INPUTS are cut off
+        let terms = [0.125, 0.25, 0.375, 0.5, 0.625].map(payload);
+        let terms = terms.iter().map(|t| &t[..]).collect::<Vec<&[Base]>>();
+        let seq = gene_ancestor(&payload(0.75), &payload(0.5), &terms);
+        let seq = duplicate_segment(&seq, 0, gene_len);
+        let body = build_genes(&seq, &mut rng);
+        assert_eq!(body.stimulus_response_vector[0], 1.0);
+        assert_eq!(body.learning_rule, [0.125, 0.25, 0.375, 0.5]);
+
+        // No functional copy reads as 0
+        assert_eq!(build_genes(&vec![A; 20], &mut rng).learning_factor, 0.0);
+    }
+}
@@ -0,0 +1,405 @@
+// E11: Self-replicating programs (Avida/Tierra style)
+//
+// Each genome is a program for a small heads-based virtual CPU. Nothing copies the genome
+// for the organism: it only reproduces if its own program allocates space, copies itself
+// instruction by instruction and divides. Mutations happen as copy errors.
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::evol_prim::*;
+
+// Instructions executed per organism per simulation step
+pub const CPU_BUDGET: usize = 30;
+// Chance each HCopy writes a random instruction instead of the one read
+pub const COPY_MUTATION_PROB: f32 = 0.0075;
+pub const DEATH_PROB: f32 = 0.1;
+// Smallest program (in instructions) a divide may produce, for either parent or child
+const MIN_PROGRAM_LEN: usize = 8;
+const MAX_PROGRAM_LEN: usize = 512;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Inst {
+    // Nops do nothing when executed. Following another instruction they select which
+    // register/head it acts on, and runs of them form labels (templates).
+    NopA,
+    NopB,
+    NopC,
+    // Execute the next instruction only if ?BX? != its next register
+    IfNEqu,
+    // Execute the next instruction only if ?BX? < its next register
+    IfLess,
+    // Execute the next instruction only if the most recently copied instructions are the
+    // complement of the following label
+    IfLabel,
+    Inc,
+    Dec,
+    Swap,
+    // Extend memory to twice the program length to make room for a child; AX = old length
+    HAlloc,
+    // Split memory between the read and write heads off as a child
+    HDivide,
+    // Copy the instruction at the read head to the write head, advancing both. The write
+    // head must be in the space HAlloc added; otherwise the copy fails, that space is freed
+    // and the program restarts, so a copy can never overwrite the parent's program.
+    HCopy,
+    // Find the complement of the following label; flow head goes to just after it
+    HSearch,
+    // Move ?IP? to the flow head
+    MovHead,
+    // Move ?IP? forward by CX
+    JmpHead,
+    // Move the flow head to ?CX?
+    SetFlow,
+}
+
+use Inst::*;
+
+const INSTRUCTIONS: [Inst; 16] = [
+    NopA, NopB, NopC, IfNEqu, IfLess, IfLabel, Inc, Dec, Swap, HAlloc, HDivide, HCopy, HSearch,
+    MovHead, JmpHead, SetFlow,
+];
+
+impl Inst {
+    fn is_nop(&self) -> bool {
+        matches!(self, NopA | NopB | NopC)
+    }
+
+    // NopA -> NopB -> NopC -> NopA
+    fn complement(&self) -> Inst {
+        match self {
+            NopA => NopB,
+            NopB => NopC,
+            NopC => NopA,
+            other => *other,
+        }
+    }
+
+    // Register (AX, BX, CX) or head (IP, read, write) index selected by a nop
+    fn nop_index(&self) -> Option<usize> {
+        match self {
+            NopA => Some(0),
+            NopB => Some(1),
+            NopC => Some(2),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Each instruction is a codon of two bases: the first base selects the high 2 bits and the
+ * second the low 2 bits of the instruction number. A trailing odd base is ignored.
+ */
+pub fn decode_program(seq: &BaseSeq) -> Vec<Inst> {
+    seq.chunks_exact(2)
+        .map(|c| INSTRUCTIONS[(c[0] as usize) * 4 + c[1] as usize])
+        .collect()
+}
+
+pub fn encode_program(program: &[Inst]) -> BaseSeq {
+    program
+        .iter()
+        .flat_map(|i| {
+            let n = INSTRUCTIONS.iter().position(|x| x == i).unwrap();
+            [BASES[n / 4], BASES[n % 4]]
+        })
+        .collect()
+}
+
+// Hand-written replicator: allocate, find the end of the program, then copy until the end
+// label has been copied and divide
+pub fn ancestor() -> BaseSeq {
+    encode_program(&[
+        HAlloc, HSearch, NopC, NopA, // flow -> end of program
+        MovHead, NopC, // write head -> flow
+        HSearch, // no label: flow -> next instruction
+        HCopy, IfLabel, NopC, NopA, // copied the end label yet?
+        HDivide, MovHead, NopA, NopB, // else jump back to HCopy; NopA NopB is the end label
+    ])
+}
+
+const IP: usize = 0;
+const READ: usize = 1;
+const WRITE: usize = 2;
+const FLOW: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct Body11 {
+    pub memory: Vec<Inst>,
+    // AX, BX, CX
+    pub registers: [i32; 3],
+    // IP, read, write, flow
+    pub heads: [usize; 4],
+    pub allocated: bool,
+    // Start of the space HAlloc added for the child
+    pub child_start: usize,
+    // Chance each HCopy writes a random instruction instead of the one read
+    pub copy_mutation_prob: f32,
+    // Instructions copied since the last divide
+    pub copied: Vec<Inst>,
+    // Genomes divided off since the last reproduction, released by reproduce
+    pub offspring: Vec<BaseSeq>,
+    pub instructions_executed: u64,
+}
+
+impl Body11 {
+    fn reset_cpu(&mut self) {
+        self.registers = [0; 3];
+        self.heads = [0; 4];
+        self.allocated = false;
+        self.child_start = 0;
+        self.copied.clear();
+    }
+
+    fn wrap(&self, pos: usize) -> usize {
+        pos % self.memory.len()
+    }
+
+    fn inst_at(&self, pos: usize) -> Inst {
+        self.memory[self.wrap(pos)]
+    }
+
+    // Nop directly after the IP, consumed as a modifier if present
+    fn modifier(&mut self) -> Option<usize> {
+        let index = self.inst_at(self.heads[IP] + 1).nop_index();
+        if index.is_some() {
+            self.heads[IP] = self.wrap(self.heads[IP] + 1);
+        }
+        index
+    }
+
+    // Run of nops directly after the IP, consumed as a label
+    fn label(&mut self) -> Vec<Inst> {
+        let mut label = Vec::new();
+        while label.len() < self.memory.len() && self.inst_at(self.heads[IP] + 1).is_nop() {
+            label.push(self.inst_at(self.heads[IP] + 1));
+            self.heads[IP] = self.wrap(self.heads[IP] + 1);
+        }
+        label
+    }
+
+    fn skip_next(&mut self) {
+        self.heads[IP] = self.wrap(self.heads[IP] + 1);
+    }
+
+    fn search(&self, label: &[Inst]) -> Option<usize> {
+        let target = label.iter().map(|i| i.complement()).collect::<Vec<Inst>>();
+        let len = self.memory.len();
+        (1..len)
+            .map(|d| self.heads[IP] + d)
+            .find(|start| (0..target.len()).all(|k| self.inst_at(start + k) == target[k]))
+            .map(|start| start - self.heads[IP])
+    }
+
+    // Returns whether a child was produced
+    fn divide(&mut self) -> bool {
+        let (read, write) = (self.heads[READ], self.heads[WRITE]);
+        if !self.allocated || read < MIN_PROGRAM_LEN || write < read + MIN_PROGRAM_LEN {
+            return false;
+        }
+        let child = self.memory[read..write].to_vec();
+        self.memory.truncate(read);
+        self.offspring.push(encode_program(&child));
+        self.reset_cpu();
+        true
+    }
+
+    // Execute one instruction. Returns false if the program cannot run at all.
+    pub fn execute(&mut self, rng: &mut ThreadRng) -> bool {
+        if self.memory.is_empty() {
+            return false;
+        }
+        self.instructions_executed += 1;
+        let mut advance = true;
+
+        match self.inst_at(self.heads[IP]) {
+            NopA | NopB | NopC => {}
+            IfNEqu => {
+                let r = self.modifier().unwrap_or(1);
+                if self.registers[r] == self.registers[(r + 1) % 3] {
+                    self.skip_next();
+                }
+            }
+            IfLess => {
+                let r = self.modifier().unwrap_or(1);
+                if self.registers[r] >= self.registers[(r + 1) % 3] {
+                    self.skip_next();
+                }
+            }
+            IfLabel => {
+                let label = self.label();
+                let matched = label.len() <= self.copied.len()
+                    && self.copied[self.copied.len() - label.len()..]
+                        .iter()
+                        .zip(label.iter())
+                        .all(|(c, l)| *c == l.complement());
+                if !matched {
+                    self.skip_next();
+                }
+            }
+            Inc => {
+                let r = self.modifier().unwrap_or(1);
+                self.registers[r] = self.registers[r].wrapping_add(1);
+            }
+            Dec => {
+                let r = self.modifier().unwrap_or(1);
+                self.registers[r] = self.registers[r].wrapping_sub(1);
+            }
+            Swap => {
+                let r = self.modifier().unwrap_or(1);
+                self.registers.swap(r, (r + 1) % 3);
+            }
+            HAlloc => {
+                let len = self.memory.len();
+                if !self.allocated && len * 2 <= MAX_PROGRAM_LEN {
+                    self.memory.resize(len * 2, NopA);
+                    self.registers[0] = len as i32;
+                    self.allocated = true;
+                    self.child_start = len;
+                }
+            }
+            HDivide => {
+                // A successful divide restarts the program from the beginning
+                advance = !self.divide();
+            }
+            HCopy => {
+                let (read, write) = (self.wrap(self.heads[READ]), self.heads[WRITE]);
+                if !self.allocated || write < self.child_start || write >= self.memory.len() {
+                    if self.allocated {
+                        self.memory.truncate(self.child_start);
+                    }
+                    self.reset_cpu();
+                    return true;
+                }
+                let inst = if rng.gen::<f32>() < self.copy_mutation_prob {
+                    INSTRUCTIONS[rng.gen_range(0..INSTRUCTIONS.len())]
+                } else {
+                    self.memory[read]
+                };
+                self.memory[write] = inst;
+                self.copied.push(inst);
+                self.heads[READ] = read + 1;
+                self.heads[WRITE] = write + 1;
+            }
+            HSearch => {
+                let label = self.label();
+                match self.search(&label).filter(|_| !label.is_empty()) {
+                    Some(distance) => {
+                        self.registers[1] = distance as i32;
+                        self.registers[2] = label.len() as i32;
+                        self.heads[FLOW] = self.wrap(self.heads[IP] + distance + label.len());
+                    }
+                    None => {
+                        self.registers[1] = 0;
+                        self.registers[2] = 0;
+                        self.heads[FLOW] = self.wrap(self.heads[IP] + 1);
+                    }
+                }
+            }
+            MovHead => {
+                let h = self.modifier().unwrap_or(IP);
+                self.heads[h] = self.heads[FLOW];
+                advance = h != IP;
+            }
+            JmpHead => {
+                let h = self.modifier().unwrap_or(IP);
+                let jump = self.registers[2].rem_euclid(self.memory.len() as i32) as usize;
+                self.heads[h] = self.wrap(self.heads[h] + jump);
+            }
+            SetFlow => {
+                let r = self.modifier().unwrap_or(2);
+                let pos = self.registers[r].rem_euclid(self.memory.len() as i32) as usize;
+                self.heads[FLOW] = pos;
+            }
+        }
+
+        if advance {
+            self.heads[IP] = self.wrap(self.heads[IP] + 1);
+        }
+        true
+    }
+}
+
+pub fn build(seq: &BaseSeq, _: &mut ThreadRng) -> Body11 {
+    Body11 {
+        memory: decode_program(seq),
+        registers: [0; 3],
+        heads: [0; 4],
+        allocated: false,
+        child_start: 0,
+        copy_mutation_prob: COPY_MUTATION_PROB,
+        copied: Vec::new(),
+        offspring: Vec::new(),
+        instructions_executed: 0,
+    }
+}
+
+pub fn death(org: &Organism<Body11>, _: &(), rng: &mut ThreadRng) -> bool {
+    org.body.memory.is_empty() || rng.gen::<f32>() < DEATH_PROB
+}
+
+// Children come only from divides the program performed during its last update
+pub fn reproduce(org: &Organism<Body11>, _: &(), _: &mut ThreadRng) -> Vec<BaseSeq> {
+    org.body.offspring.clone()
+}
+
+pub fn update(org: &mut Organism<Body11>, _: &(), rng: &mut ThreadRng) {
+    org.body.offspring.clear();
+    for _ in 0..CPU_BUDGET {
+        if !org.body.execute(rng) {
+            break;
+        }
+    }
+}
+
+pub fn learn(_: &mut Organism<Body11>, _: &(), _: &mut ThreadRng) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run until the first divide, or give up after max_instructions
+    fn run_until_divide(body: &mut Body11, max_instructions: usize) -> Option<BaseSeq> {
+        let mut rng = rand::thread_rng();
+        for _ in 0..max_instructions {
+            body.execute(&mut rng);
+            if let Some(child) = body.offspring.pop() {
+                return Some(child);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn program_encoding_round_trips() {
+        assert_eq!(decode_program(&encode_program(&INSTRUCTIONS)), INSTRUCTIONS);
+        let seq = (0..64)
+            .map(|i| BASES[(i * 7 + i / 3) % 4])
+            .collect::<BaseSeq>();
+        assert_eq!(encode_program(&decode_program(&seq)), seq);
+    }
+
+    #[test]
+    fn ancestor_replicates_exactly_without_mutation() {
+        let mut body = build(&ancestor(), &mut rand::thread_rng());
+        body.copy_mutation_prob = 0.0;
+        for _ in 0..3 {
+            assert_eq!(run_until_divide(&mut body, 1000), Some(ancestor()));
+            assert_eq!(encode_program(&body.memory), ancestor());
+        }
+    }
+
+    #[test]
+    fn runaway_copy_does_not_overwrite_the_parent() {
+        // The IfLabel waits for NopC NopC, which the program never copies, and the read head
+        // starts 2 in (JmpHead by CX = 2), so a copy wrapping round would shift the parent
+        let program = [
+            HAlloc, HSearch, NopC, NopA, JmpHead, NopB, MovHead, NopC, HSearch, HCopy, IfLabel,
+            NopB, NopB, HDivide, MovHead, NopA, NopB,
+        ];
+        let mut body = build(&encode_program(&program), &mut rand::thread_rng());
+        body.copy_mutation_prob = 0.0;
+        assert_eq!(run_until_divide(&mut body, 2000), None);
+        assert_eq!(body.memory[..program.len()], program);
+    }
+}
@@ -0,0 +1,133 @@
+// E12: Transposons in a prefix replicator
+//
+// As E1, organisms replicate only if they start with E1_REPRODUCE_PREFIX. Genomes may also
+// carry copies of a transposable element, which spread through the genome each time it is
+// cloned. Every copy raises the chance of death, and a copy landing in the prefix stops
+// replication, so the element spreads at its host's expense. run records the transposon load
+// after every step, to follow the element's spread over time.
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::e1::E1_REPRODUCE_PREFIX;
+use crate::evol_prim::Base::*;
+use crate::evol_prim::*;
+use crate::sim::Simulation;
+use crate::transposon::{clone_with_transposition, Transposon, TransposonLoad};
+
+pub const TRANSPOSON_MOTIF: &[Base] = &[C, G, G, C, C, G];
+pub const TRANSPOSITION_RATE: f32 = 0.5;
+pub const DEATH_PROB: f32 = 0.5;
+// Extra death probability per transposon copy
+pub const COPY_COST: f32 = 0.01;
+
+pub fn transposon() -> Transposon {
+    Transposon {
+        motif: TRANSPOSON_MOTIF,
+        rate: TRANSPOSITION_RATE,
+    }
+}
+
+// The replication prefix, `len` random bases and one transposon copy
+pub fn ancestor(len: usize, rng: &mut ThreadRng) -> BaseSeq {
+    let mut seq = E1_REPRODUCE_PREFIX.to_vec();
+    seq.extend((0..len).map(|_| rng.gen::<Base>()));
+    seq.extend_from_slice(TRANSPOSON_MOTIF);
+    seq
+}
+
+pub fn reproduce(org: &Organism<()>, _: &(), rng: &mut ThreadRng) -> Vec<BaseSeq> {
+    if !org.genes.starts_with(E1_REPRODUCE_PREFIX) {
+        return Vec::new();
+    }
+    let transposons = [transposon()];
+    (0..2)
+        .map(|_| clone_with_transposition(&org.genes, rng, &transposons, 0.01, 0.01, 0.05))
+        .collect()
+}
+
+// DEATH_PROB plus COPY_COST for every transposon copy in seq
+pub fn death_prob(seq: &BaseSeq) -> f32 {
+    DEATH_PROB + COPY_COST * transposon().count(seq) as f32
+}
+
+pub fn death(org: &Organism<()>, _: &(), rng: &mut ThreadRng) -> bool {
+    org.genes.is_empty() || rng.gen::<f32>() < death_prob(&org.genes)
+}
+
+pub fn build(_: &BaseSeq, _: &mut ThreadRng) {}
+
+pub fn no_update(_: &mut Organism<()>, _: &(), _: &mut ThreadRng) {}
+
+// Simulation of the given ancestors with E12's rules and no environment
+pub fn simulation(
+    ancestors: Vec<BaseSeq>,
+    max_sequences: usize,
+    max_t: i32,
+) -> Simulation<'static, (), ()> {
+    Simulation {
+        R: &reproduce,
+        D: &death,
+        B: &build,
+        U: &no_update,
+        L: &no_update,
+        organisms: ancestors
+            .into_iter()
+            .map(|s| Organism::new(s, (), 0))
+            .collect(),
+        environment: (),
+        max_sequences,
+        t: 0,
+        max_t,
+        rng: rand::thread_rng(),
+        speciation: None,
+        hgt: None,
+        tracker: None,
+        elitism: None,
+        hall_of_fame: None,
+        soup: None,
+    }
+}
+
+// Run simulation for `steps` steps, returning the transposon load after each
+pub fn run(simulation: &mut Simulation<(), ()>, steps: usize) -> Vec<TransposonLoad> {
+    let transposon = transposon();
+    (0..steps)
+        .map(|_| {
+            let t = simulation.t;
+            simulation.run_step();
+            TransposonLoad::compute(&simulation.organisms, &transposon, t)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn death_rises_with_copies() {
+        let mut rng = rand::thread_rng();
+        let mut seq = ancestor(8, &mut rng);
+        let mut last = death_prob(&seq);
+        for _ in 0..3 {
+            seq.extend_from_slice(TRANSPOSON_MOTIF);
+            assert!(death_prob(&seq) > last);
+            last = death_prob(&seq);
+        }
+        assert!((last - (DEATH_PROB + 4.0 * COPY_COST)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn run_records_the_load_of_every_step() {
+        let mut rng = rand::thread_rng();
+        let ancestors = (0..20).map(|_| ancestor(8, &mut rng)).collect();
+        let mut sim = simulation(ancestors, 50, 10);
+        let loads = run(&mut sim, 10);
+        assert_eq!(
+            loads.iter().map(|l| l.t).collect::<Vec<i32>>(),
+            (0..10).collect::<Vec<i32>>()
+        );
+        assert_eq!(sim.t, 10);
+    }
+}
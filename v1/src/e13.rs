@@ -0,0 +1,210 @@
+// E13: The README learning circuit
+//
+// The organism is built from the README's blocks, wired as its interaction matrix:
+//   s  stimulus reception (fixed, as in E10)
+//   n  selection-only response, a genome-encoded circuit from s
+//   l  learned response, a value written by u (output of u assigned to l)
+//   a  action (the organism's position), a genome-encoded circuit from n and l
+//   r  reward/death, from a. As the README allows for now, reward and death are one
+//      function: r is 1 at the centre of the safe zone, falling to 0 a distance 1 away
+//   u  update circuit, genome-encoded, from s and r
+// Learning runs {s -> (n, l) -> a -> r} then {(s, r) -> u} and assigns u to l, LEARNING_STEPS
+// times. Selection then runs {s -> (n, l) -> a -> r} once, and the organism survives with
+// probability r.
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::e10::Environment10;
+use crate::evol_prim::*;
+
+pub const LEARNING_STEPS: usize = 3;
+// Children per step spent in the safe zone
+pub const CHILDREN: usize = 2;
+// Circuit weights and biases are genome bytes in [-1, 1] times this
+pub const PARAM_SCALE: f32 = 2.0;
+
+// Weighted sum of inputs plus bias, clamped to [-1, 1]
+#[derive(Debug, Clone)]
+pub struct Circuit {
+    pub weights: Vec<f32>,
+    pub bias: f32,
+}
+
+impl Circuit {
+    fn read(inputs: usize, bases: &mut dyn Iterator<Item = &Base>) -> Self {
+        let mut param = || byte_to_feature_space(read4_bases_to_unsigned_byte(bases)) * PARAM_SCALE;
+        let weights = (0..inputs).map(|_| param()).collect();
+        Circuit {
+            weights,
+            bias: param(),
+        }
+    }
+
+    pub fn run(&self, inputs: &[f32]) -> f32 {
+        let sum = self
+            .weights
+            .iter()
+            .zip(inputs)
+            .map(|(w, x)| w * x)
+            .sum::<f32>();
+        (sum + self.bias).clamp(-1.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Body13 {
+    pub n: Circuit,
+    pub a: Circuit,
+    pub u: Circuit,
+    // Learned response, as last assigned by u
+    pub l: f32,
+    pub position: f32,
+    // r as of the last act; 1 before the first, so organisms are not judged before acting
+    pub reward: f32,
+}
+
+fn zone_centre(env: &Environment10) -> f32 {
+    env.safe_zone_low + ((env.safe_zone_high - env.safe_zone_low) / 2.0)
+}
+
+// r: 1 at the centre of the safe zone, falling linearly to 0 a distance 1 away
+pub fn reward(position: f32, env: &Environment10) -> f32 {
+    (1.0 - wrapping_dist(position, zone_centre(env)).abs()).max(0.0)
+}
+
+fn stimulus_reception(env: &Environment10) -> f32 {
+    zone_centre(env) * 3.0
+}
+
+// {s -> (n, l) -> a -> r}, setting position and reward
+fn act(body: &mut Body13, env: &Environment10) {
+    let s = stimulus_reception(env);
+    let n = body.n.run(&[s]);
+    body.position = body.a.run(&[n, body.l]);
+    body.reward = reward(body.position, env);
+}
+
+/**
+ * Genome bytes in order: n (w_s, bias), l (initial value), a (w_n, w_l, bias),
+ * u (w_s, w_r, bias). Missing bases read as 0.
+ */
+pub fn build(seq: &BaseSeq, _: &mut ThreadRng) -> Body13 {
+    let mut bases = seq.iter();
+    let n = Circuit::read(1, &mut bases);
+    let l = byte_to_feature_space(read4_bases_to_unsigned_byte(&mut bases));
+    let a = Circuit::read(2, &mut bases);
+    let u = Circuit::read(2, &mut bases);
+    Body13 {
+        n,
+        a,
+        u,
+        l,
+        position: 0.0,
+        reward: 1.0,
+    }
+}
+
+pub fn learn(org: &mut Organism<Body13>, env: &Environment10, _: &mut ThreadRng) {
+    let body = &mut org.body;
+    let s = stimulus_reception(env);
+    for _ in 0..LEARNING_STEPS {
+        act(body, env);
+        body.l = body.u.run(&[s, body.reward]);
+    }
+}
+
+pub fn update(org: &mut Organism<Body13>, env: &Environment10, _: &mut ThreadRng) {
+    act(&mut org.body, env);
+}
+
+// Lives or dies based on r, as set by the last act
+pub fn death(org: &Organism<Body13>, _: &Environment10, rng: &mut ThreadRng) -> bool {
+    rng.gen::<f32>() >= org.body.reward
+}
+
+pub fn reproduce(org: &Organism<Body13>, env: &Environment10, rng: &mut ThreadRng) -> Vec<BaseSeq> {
+    if in_zone_possibly_wrapped(org.body.position, env.safe_zone_low, env.safe_zone_high) {
+        (0..CHILDREN)
+            .map(|_| clone_with_mutation(&org.genes, rng, 0.0, 0.0, 0.02))
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Safe zone centred on 0, so s is 0 too
+    fn env() -> Environment10 {
+        Environment10 {
+            safe_zone_low: -0.25,
+            safe_zone_high: 0.25,
+        }
+    }
+
+    fn circuit(weights: &[f32], bias: f32) -> Circuit {
+        Circuit {
+            weights: weights.to_vec(),
+            bias,
+        }
+    }
+
+    fn bytes(features: &[f32]) -> BaseSeq {
+        features
+            .iter()
+            .flat_map(|f| unsigned_byte_to_4_bases(feature_space_to_byte(*f)))
+            .collect()
+    }
+
+    #[test]
+    fn build_reads_n_l_a_u_in_order() {
+        let mut rng = rand::thread_rng();
+        // u's w_r and bias are missing
+        let seq = bytes(&[0.25, -0.5, 0.5, 0.125, 0.75, -0.25, 0.5]);
+        let body = build(&seq, &mut rng);
+        assert_eq!((body.n.weights, body.n.bias), (vec![0.5], -1.0));
+        assert_eq!(body.l, 0.5);
+        assert_eq!((body.a.weights, body.a.bias), (vec![0.25, 1.5], -0.5));
+        assert_eq!((body.u.weights, body.u.bias), (vec![1.0, 0.0], 0.0));
+        assert_eq!(body.reward, 1.0);
+    }
+
+    #[test]
+    fn learn_assigns_u_to_l_every_step() {
+        let mut rng = rand::thread_rng();
+        // a = l and u = r / 2, so l: 0.5 -> 0.25 -> 0.375 -> 0.3125
+        let body = Body13 {
+            n: circuit(&[0.0], 0.0),
+            a: circuit(&[0.0, 1.0], 0.0),
+            u: circuit(&[0.0, 0.5], 0.0),
+            l: 0.5,
+            position: 0.0,
+            reward: 1.0,
+        };
+        let mut org = Organism::new(Vec::new(), body, 0);
+        assert_eq!(LEARNING_STEPS, 3);
+        learn(&mut org, &env(), &mut rng);
+        assert_eq!(org.body.l, 0.3125);
+        assert_eq!((org.body.position, org.body.reward), (0.375, 0.625));
+    }
+
+    #[test]
+    fn reward_falls_from_the_zone_centre() {
+        assert_eq!(reward(0.0, &env()), 1.0);
+        assert_eq!(reward(0.5, &env()), 0.5);
+        assert_eq!(reward(-1.0, &env()), 0.0);
+        assert_eq!(reward(1.0, &env()), 0.0);
+    }
+
+    #[test]
+    fn death_never_fires_at_full_reward() {
+        let mut rng = rand::thread_rng();
+        let mut org = Organism::new(Vec::new(), build(&Vec::new(), &mut rng), 0);
+        assert!((0..1000).all(|_| !death(&org, &env(), &mut rng)));
+        org.body.reward = 0.0;
+        assert!((0..1000).all(|_| death(&org, &env(), &mut rng)));
+    }
+}
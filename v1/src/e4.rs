@@ -21,6 +21,28 @@ pub fn reproduce(s: &BaseSeq, rng: &mut ThreadRng) -> Vec<BaseSeq> {
     }
 }
 
+/**
+ * Mutation rates encoded in the last 12 bases of the genome, one byte each for insertion,
+ * deletion and base change, spanning the rates C protection gives up to four times the
+ * unprotected ones. reproduce_encoded reads them in place of the C count, so protection from
+ * mutation evolves through the rate loci rather than through a hard-coded rule.
+ */
+pub const RATE_LOCI: MutationRateLoci = MutationRateLoci {
+    insertion: EncodedRate::new(Locus::FromEnd(12), 0.0005, 0.04),
+    deletion: EncodedRate::new(Locus::FromEnd(8), 0.0005, 0.04),
+    base_change: EncodedRate::new(Locus::FromEnd(4), 0.0025, 0.2),
+};
+
+// reproduce, with the parent's own encoded mutation rates (RATE_LOCI) instead of C protection
+pub fn reproduce_encoded(s: &BaseSeq, rng: &mut ThreadRng) -> Vec<BaseSeq> {
+    match count_AT_repetitions(s) {
+        0 | 1 => Vec::new(),
+        at_reps => (0..at_reps)
+            .map(|_| clone_with_encoded_mutation(s, rng, &RATE_LOCI))
+            .collect(),
+    }
+}
+
 pub fn death(s: &BaseSeq, rng: &mut ThreadRng) -> bool {
     s.len() == 0 || rng.gen::<f32>() < 0.5
 }
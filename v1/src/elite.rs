@@ -0,0 +1,245 @@
+// Elitism and a hall-of-fame archive of the best genotypes seen during a run
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::evol_prim::*;
+use crate::fasta::{read_fasta_records, write_fasta_record};
+
+pub type Score<'a, O, E> = &'a dyn Fn(&Organism<O>, &E) -> f32;
+
+// Organisms scoring in the top `count` at the start of a step skip death that step and are
+// never dropped when the population is capped
+pub struct Elitism<'a, O, E> {
+    pub count: usize,
+    pub score: Score<'a, O, E>,
+}
+
+impl<'a, O, E> Clone for Elitism<'a, O, E> {
+    fn clone(&self) -> Self {
+        Elitism {
+            count: self.count,
+            score: self.score,
+        }
+    }
+}
+
+impl<'a, O, E> Elitism<'a, O, E> {
+    pub fn elite_ids(&self, organisms: &[Organism<O>], env: &E) -> HashSet<u64> {
+        let mut scored = organisms
+            .iter()
+            .map(|o| ((self.score)(o, env), o.id))
+            .collect::<Vec<(f32, u64)>>();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.iter().take(self.count).map(|(_, id)| *id).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HallOfFameEntry {
+    pub score: f32,
+    // Step the score was achieved
+    pub t: i32,
+    pub id: u64,
+    pub genes: BaseSeq,
+}
+
+// Top `capacity` distinct genotypes ever seen, best first
+pub struct HallOfFame<'a, O, E> {
+    pub capacity: usize,
+    pub score: Score<'a, O, E>,
+    pub entries: Vec<HallOfFameEntry>,
+    // Written by Simulation::run when the run ends
+    pub save_path: Option<PathBuf>,
+}
+
+impl<'a, O, E> Clone for HallOfFame<'a, O, E> {
+    fn clone(&self) -> Self {
+        HallOfFame {
+            capacity: self.capacity,
+            score: self.score,
+            entries: self.entries.clone(),
+            save_path: self.save_path.clone(),
+        }
+    }
+}
+
+impl<'a, O, E> HallOfFame<'a, O, E> {
+    pub fn new(capacity: usize, score: Score<'a, O, E>, save_path: Option<PathBuf>) -> Self {
+        HallOfFame {
+            capacity,
+            score,
+            entries: Vec::new(),
+            save_path,
+        }
+    }
+
+    pub fn consider(&mut self, organisms: &[Organism<O>], env: &E, t: i32) {
+        for org in organisms {
+            self.insert(HallOfFameEntry {
+                score: (self.score)(org, env),
+                t,
+                id: org.id,
+                genes: org.genes.clone(),
+            });
+        }
+    }
+
+    // Add an entry unless its genotype is already in with at least its score
+    fn insert(&mut self, entry: HallOfFameEntry) {
+        match self.entries.iter_mut().find(|e| e.genes == entry.genes) {
+            // Same genotype: keep its best score
+            Some(existing) => {
+                if entry.score > existing.score {
+                    *existing = entry;
+                }
+            }
+            None => {
+                if self.entries.len() < self.capacity
+                    || self
+                        .entries
+                        .last()
+                        .is_some_and(|worst| entry.score > worst.score)
+                {
+                    self.entries.push(entry);
+                }
+            }
+        }
+        self.entries.sort_by(|a, b| b.score.total_cmp(&a.score));
+        self.entries.truncate(self.capacity);
+    }
+
+    /**
+     * Save as FASTA with headers `>{id} birth={t} score={score}`, so the archive can be
+     * reloaded for re-evaluation with fasta::read_fasta or HallOfFame::load.
+     */
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for e in &self.entries {
+            let header = format!("{} birth={} score={}", e.id, e.t, e.score);
+            write_fasta_record(&mut writer, &header, &e.genes)?;
+        }
+        writer.flush()
+    }
+
+    // Add the entries of a saved archive, merging genotypes already present as consider does
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut reader = BufReader::new(File::open(path)?);
+        for r in read_fasta_records(&mut reader)? {
+            let score = r
+                .header
+                .split_whitespace()
+                .find_map(|f| f.strip_prefix("score="))
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(f32::NEG_INFINITY);
+            self.insert(HallOfFameEntry {
+                score,
+                t: r.birth_step.unwrap_or(0),
+                id: r.id.unwrap_or(0),
+                genes: r.genes,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use rand::prelude::ThreadRng;
+
+    use super::*;
+    use crate::evol_prim::Base::*;
+    use crate::sim::Simulation;
+
+    #[test]
+    fn reloading_an_archive_does_not_duplicate_genotypes() {
+        let score = |o: &Organism<f32>, _: &()| o.body;
+        let mut hof = HallOfFame::new(3, &score, None);
+        let organisms = vec![
+            Organism::new(vec![A, A], 1.0, 0),
+            Organism::new(vec![C, C], 2.0, 0),
+            Organism::new(vec![A, A], 0.5, 0),
+        ];
+        hof.consider(&organisms, &(), 4);
+        assert_eq!(hof.entries.len(), 2);
+
+        let path = env::temp_dir().join(format!("hall_of_fame_{}.fasta", std::process::id()));
+        hof.save(&path).unwrap();
+        hof.load(&path).unwrap();
+        hof.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(hof.entries.len(), 2);
+        assert_eq!(hof.entries[0].genes, vec![C, C]);
+        assert_eq!(hof.entries[0].score, 2.0);
+        assert_eq!(hof.entries[1].score, 1.0);
+        assert_eq!(hof.entries[1].t, 4);
+    }
+
+    // Organisms scored by their body, which never reproduce
+    fn simulation<'a>(
+        bodies: &[f32],
+        dies: &'a dyn Fn(&Organism<f32>, &(), &mut ThreadRng) -> bool,
+        max_sequences: usize,
+        score: Score<'a, f32, ()>,
+    ) -> Simulation<'a, f32, ()> {
+        Simulation {
+            R: &|_: &Organism<f32>, _: &(), _: &mut ThreadRng| Vec::new(),
+            D: dies,
+            B: &|_: &BaseSeq, _: &mut ThreadRng| 0.0,
+            U: &|_: &mut Organism<f32>, _: &(), _: &mut ThreadRng| {},
+            L: &|_: &mut Organism<f32>, _: &(), _: &mut ThreadRng| {},
+            organisms: bodies
+                .iter()
+                .map(|b| Organism::new(vec![A], *b, 0))
+                .collect(),
+            environment: (),
+            max_sequences,
+            t: 0,
+            max_t: 1,
+            rng: rand::thread_rng(),
+            speciation: None,
+            hgt: None,
+            tracker: None,
+            elitism: Some(Elitism { count: 2, score }),
+            hall_of_fame: None,
+            soup: None,
+        }
+    }
+
+    fn ids_by_body(sim: &Simulation<f32, ()>, bodies: &[f32]) -> HashSet<u64> {
+        sim.organisms
+            .iter()
+            .filter(|o| bodies.contains(&o.body))
+            .map(|o| o.id)
+            .collect()
+    }
+
+    #[test]
+    fn elites_skip_death() {
+        let score = |o: &Organism<f32>, _: &()| o.body;
+        let mut sim = simulation(&[0.1, 0.9, 0.5, 0.7], &|_, _, _| true, 10, &score);
+        let elites = ids_by_body(&sim, &[0.9, 0.7]);
+        sim.run_step();
+        let survivors = sim.organisms.iter().map(|o| o.id).collect::<HashSet<u64>>();
+        assert_eq!(survivors, elites);
+    }
+
+    #[test]
+    fn elites_are_kept_at_the_cap() {
+        let score = |o: &Organism<f32>, _: &()| o.body;
+        for _ in 0..20 {
+            let bodies = (0..10).map(|i| i as f32).collect::<Vec<f32>>();
+            let mut sim = simulation(&bodies, &|_, _, _| false, 3, &score);
+            let elites = ids_by_body(&sim, &[9.0, 8.0]);
+            sim.run_step();
+            assert_eq!(sim.organisms.len(), 3);
+            let kept = sim.organisms.iter().map(|o| o.id).collect::<HashSet<u64>>();
+            assert!(kept.is_superset(&elites));
+        }
+    }
+}
@@ -1,5 +1,9 @@
 // Evolution Primitives
 
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use rand::distributions::{Distribution, Standard};
 use rand::{prelude::ThreadRng, Rng};
 
@@ -7,7 +11,7 @@ pub type BaseSeq = Vec<Base>;
 
 use Base::*;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Base {
     A,
     C,
@@ -15,6 +19,9 @@ pub enum Base {
     G,
 }
 
+// Every base, in order of Base number
+pub const BASES: [Base; 4] = [A, C, T, G];
+
 impl Distribution<Base> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Base {
         match rng.gen_range(0..4) {
@@ -26,6 +33,79 @@ impl Distribution<Base> for Standard {
     }
 }
 
+impl fmt::Display for Base {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = match self {
+            A => 'A',
+            C => 'C',
+            T => 'T',
+            G => 'G',
+        };
+        write!(f, "{}", c)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseBaseError {
+    Invalid(char),
+    // Parsing a single base from a string holding none
+    Empty,
+}
+
+impl fmt::Display for ParseBaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseBaseError::Invalid(c) => {
+                write!(f, "invalid base '{}', expected one of A, C, T, G", c)
+            }
+            ParseBaseError::Empty => write!(f, "no base given, expected one of A, C, T, G"),
+        }
+    }
+}
+
+impl std::error::Error for ParseBaseError {}
+
+impl TryFrom<char> for Base {
+    type Error = ParseBaseError;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        match c.to_ascii_uppercase() {
+            'A' => Ok(A),
+            'C' => Ok(C),
+            'T' => Ok(T),
+            'G' => Ok(G),
+            _ => Err(ParseBaseError::Invalid(c)),
+        }
+    }
+}
+
+impl FromStr for Base {
+    type Err = ParseBaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.trim().chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Base::try_from(c),
+            (Some(_), Some(c)) => Err(ParseBaseError::Invalid(c)),
+            (None, _) => Err(ParseBaseError::Empty),
+        }
+    }
+}
+
+// Parse packed text such as "ATCT" into a sequence. Whitespace is ignored so wrapped
+// (e.g. FASTA) sequence lines can be passed straight through.
+pub fn parse_seq(s: &str) -> Result<BaseSeq, ParseBaseError> {
+    s.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(Base::try_from)
+        .collect()
+}
+
+// Packed text form of a sequence, e.g. "ATCT"
+pub fn seq_to_string(seq: &[Base]) -> String {
+    seq.iter().map(|b| b.to_string()).collect()
+}
+
 pub fn clone_with_mutation(
     seq: &BaseSeq,
     rng: &mut ThreadRng,
@@ -56,12 +136,139 @@ pub fn clone_with_mutation(
     new
 }
 
+// Position of a genome-encoded value. FromEnd counts back from the end of the sequence so
+// values can sit after a prefix of varying length (e.g. the AT repeats of e3/e4).
+#[derive(Debug, Copy, Clone)]
+pub enum Locus {
+    FromStart(usize),
+    FromEnd(usize),
+}
+
+impl Locus {
+    pub fn start(&self, seq_len: usize) -> usize {
+        match self {
+            Locus::FromStart(i) => *i,
+            Locus::FromEnd(i) => seq_len.saturating_sub(*i),
+        }
+    }
+}
+
+/**
+ * A rate read from 4 bases at `locus` as one byte (missing bases treated as 0), mapped
+ * geometrically onto [min, max] so that single base changes move the rate by a roughly
+ * constant factor rather than a constant amount.
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct EncodedRate {
+    locus: Locus,
+    min: f32,
+    max: f32,
+}
+
+impl EncodedRate {
+    // Panics unless 0 < min <= max < infinity, as the geometric mapping needs
+    pub const fn new(locus: Locus, min: f32, max: f32) -> Self {
+        assert!(
+            min > 0.0 && min <= max && max.is_finite(),
+            "encoded rate bounds must satisfy 0 < min <= max < infinity"
+        );
+        EncodedRate { locus, min, max }
+    }
+
+    pub fn read(&self, seq: &BaseSeq) -> f32 {
+        let start = self.locus.start(seq.len());
+        let byte = read4_bases_to_unsigned_byte(&mut seq.iter().skip(start));
+        self.min * (self.max / self.min).powf(byte as f32 / 255.0)
+    }
+}
+
+// Genome loci encoding an organism's own mutation rates
+#[derive(Debug, Copy, Clone)]
+pub struct MutationRateLoci {
+    pub insertion: EncodedRate,
+    pub deletion: EncodedRate,
+    pub base_change: EncodedRate,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MutationRates {
+    pub insertion: f32,
+    pub deletion: f32,
+    pub base_change: f32,
+}
+
+impl MutationRateLoci {
+    pub fn read(&self, seq: &BaseSeq) -> MutationRates {
+        MutationRates {
+            insertion: self.insertion.read(seq),
+            deletion: self.deletion.read(seq),
+            base_change: self.base_change.read(seq),
+        }
+    }
+}
+
+// clone_with_mutation using the rates encoded in the parent's own genome. The rate loci are
+// copied (and mutated) along with everything else, so the rates themselves evolve.
+pub fn clone_with_encoded_mutation(
+    seq: &BaseSeq,
+    rng: &mut ThreadRng,
+    loci: &MutationRateLoci,
+) -> BaseSeq {
+    let rates = loci.read(seq);
+    clone_with_mutation(
+        seq,
+        rng,
+        rates.insertion,
+        rates.deletion,
+        rates.base_change,
+    )
+}
+
+// Population mean of the encoded mutation rates, for reporting alongside other metrics
+pub fn mean_mutation_rates<O>(organisms: &[Organism<O>], loci: &MutationRateLoci) -> MutationRates {
+    let n = organisms.len().max(1) as f32;
+    organisms.iter().map(|o| loci.read(&o.genes)).fold(
+        MutationRates {
+            insertion: 0.0,
+            deletion: 0.0,
+            base_change: 0.0,
+        },
+        |m, r| MutationRates {
+            insertion: m.insertion + r.insertion / n,
+            deletion: m.deletion + r.deletion / n,
+            base_change: m.base_change + r.base_change / n,
+        },
+    )
+}
+
 #[derive(Debug, Clone)]
 pub struct Organism<O> {
+    pub id: u64,
+    // Simulation step in which the organism was created
+    pub birth_step: i32,
     pub genes: BaseSeq,
     pub body: O,
 }
 
+static NEXT_ORGANISM_ID: AtomicU64 = AtomicU64::new(0);
+
+impl<O> Organism<O> {
+    pub fn new(genes: BaseSeq, body: O, birth_step: i32) -> Self {
+        Organism {
+            id: NEXT_ORGANISM_ID.fetch_add(1, Ordering::Relaxed),
+            birth_step,
+            genes,
+            body,
+        }
+    }
+}
+
+// Ensure ids handed out by Organism::new never collide with ids up to and including
+// `id`, e.g. after loading a saved population
+pub fn reserve_organism_ids(id: u64) {
+    NEXT_ORGANISM_ID.fetch_max(id.saturating_add(1), Ordering::Relaxed);
+}
+
 pub fn read4_bases_to_unsigned_byte(bases: &mut dyn Iterator<Item = &Base>) -> u8 {
     let mut byte: u8 = 0;
     for _ in 0..4 {
@@ -75,6 +282,17 @@ pub fn byte_to_feature_space(byte: u8) -> f32 {
     (byte.wrapping_add(128) as i32 - 128) as f32 / 128.0
 }
 
+// Inverse of read4_bases_to_unsigned_byte
+pub fn unsigned_byte_to_4_bases(byte: u8) -> [Base; 4] {
+    let base = |v: u8| BASES[(v & 3) as usize];
+    [base(byte >> 6), base(byte >> 4), base(byte >> 2), base(byte)]
+}
+
+// Nearest byte whose byte_to_feature_space is feat
+pub fn feature_space_to_byte(feat: f32) -> u8 {
+    ((feat * 128.0).round().clamp(-128.0, 127.0) as i8) as u8
+}
+
 // feat1, feat2, res in [-1,1]; feat1 + feat2 = res
 pub fn wrapping_feature_add(feat1: f32, feat2: f32) -> f32 {
     ((feat1 + feat2 + 3.0) % 2.0) - 1.0
@@ -95,6 +313,11 @@ pub trait Environment {
     fn update(&mut self, rng: &mut ThreadRng);
 }
 
+// For experiments with no environment (e.g. e0-e4)
+impl Environment for () {
+    fn update(&mut self, _: &mut ThreadRng) {}
+}
+
 pub fn in_zone_possibly_wrapped(mut pos: f32, zone_low: f32, mut zone_high: f32) -> bool {
     if zone_low > zone_high {
         // wrapped?
@@ -106,3 +329,70 @@ pub fn in_zone_possibly_wrapped(mut pos: f32, zone_low: f32, mut zone_high: f32)
 
     pos >= zone_low && pos <= zone_high
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_parse_and_display_back() {
+        let seq = parse_seq("ATCT").unwrap();
+        assert_eq!(seq, vec![A, T, C, T]);
+        assert_eq!(seq_to_string(&seq), "ATCT");
+        assert_eq!(parse_seq("at\ncg").unwrap(), vec![A, T, C, G]);
+        assert_eq!(" g ".parse::<Base>(), Ok(G));
+        assert_eq!(G.to_string(), "G");
+    }
+
+    #[test]
+    fn invalid_text_is_rejected() {
+        assert_eq!(parse_seq("ATXT"), Err(ParseBaseError::Invalid('X')));
+        assert_eq!("AT".parse::<Base>(), Err(ParseBaseError::Invalid('T')));
+        assert_eq!("".parse::<Base>(), Err(ParseBaseError::Empty));
+        assert_eq!(
+            ParseBaseError::Empty.to_string(),
+            "no base given, expected one of A, C, T, G"
+        );
+    }
+
+    #[test]
+    fn encoded_rate_spans_its_bounds() {
+        let rate = EncodedRate::new(Locus::FromEnd(4), 0.001, 0.1);
+        let read = |tail: [Base; 4]| rate.read(&[vec![C; 6], tail.to_vec()].concat());
+        assert!((read([A; 4]) - 0.001).abs() < 1e-9);
+        assert!((read([G; 4]) - 0.1).abs() < 1e-7);
+        // Each step of the byte scales the rate by the same factor
+        let (low, mid, high) = (read([T, A, A, A]), read([T, A, A, C]), read([T, A, A, T]));
+        assert!((mid / low - high / mid).abs() < 1e-5);
+        // Missing bases read as A
+        assert!((rate.read(&vec![]) - 0.001).abs() < 1e-9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn encoded_rate_rejects_zero_min() {
+        EncodedRate::new(Locus::FromStart(0), 0.0, 0.1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn encoded_rate_rejects_min_above_max() {
+        EncodedRate::new(Locus::FromStart(0), 0.2, 0.1);
+    }
+
+    #[test]
+    fn encoded_mutation_uses_the_parents_rates() {
+        let mut rng = rand::thread_rng();
+        let rate = |min, max| EncodedRate::new(Locus::FromStart(0), min, max);
+        let loci = MutationRateLoci {
+            insertion: rate(1e-9, 1.0),
+            deletion: rate(1e-9, 1.0),
+            base_change: rate(1e-9, 1.0),
+        };
+        let stable = [vec![A; 4], vec![C; 60]].concat();
+        assert_eq!(clone_with_encoded_mutation(&stable, &mut rng, &loci), stable);
+        let mutable = [vec![G; 4], vec![C; 60]].concat();
+        assert_eq!(loci.read(&mutable).base_change, 1.0);
+        assert_ne!(clone_with_encoded_mutation(&mutable, &mut rng, &loci), mutable);
+    }
+}
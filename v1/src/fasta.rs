@@ -0,0 +1,178 @@
+// Reading and writing populations as FASTA
+//
+// Each organism is written as
+//   >{id} birth={birth_step} body={body:?}
+//   ACTG... (wrapped at FASTA_LINE_WIDTH)
+// Only the id, birth step and sequence are read back; bodies are rebuilt from the sequence
+// with the experiment's build function, the body summary is for inspection only.
+
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use rand::prelude::ThreadRng;
+
+use crate::evol_prim::*;
+
+const FASTA_LINE_WIDTH: usize = 60;
+
+// `header` is written without the leading '>'
+pub fn write_fasta_record(writer: &mut dyn Write, header: &str, genes: &[Base]) -> io::Result<()> {
+    writeln!(writer, ">{}", header)?;
+    for line in genes.chunks(FASTA_LINE_WIDTH) {
+        writeln!(writer, "{}", seq_to_string(line))?;
+    }
+    Ok(())
+}
+
+pub fn write_fasta_to<O: Debug>(
+    writer: &mut dyn Write,
+    organisms: &[Organism<O>],
+) -> io::Result<()> {
+    for org in organisms {
+        let header = format!("{} birth={} body={:?}", org.id, org.birth_step, org.body);
+        write_fasta_record(writer, &header, &org.genes)?;
+    }
+    Ok(())
+}
+
+pub fn write_fasta<O: Debug>(path: impl AsRef<Path>, organisms: &[Organism<O>]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_fasta_to(&mut writer, organisms)?;
+    writer.flush()
+}
+
+#[derive(Debug, Clone)]
+pub struct FastaRecord {
+    pub id: Option<u64>,
+    pub birth_step: Option<i32>,
+    // Full header line without the leading '>'
+    pub header: String,
+    pub genes: BaseSeq,
+}
+
+fn invalid_data(line_no: usize, msg: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line_no, msg))
+}
+
+fn parse_header(header: &str) -> (Option<u64>, Option<i32>) {
+    let mut fields = header.split_whitespace();
+    let id = fields.next().and_then(|f| f.parse().ok());
+    let birth_step = fields
+        .find_map(|f| f.strip_prefix("birth="))
+        .and_then(|b| b.parse().ok());
+    (id, birth_step)
+}
+
+// Parse FASTA records. Headers written by other tools are accepted; id and birth step are
+// None when the header does not follow the format written by write_fasta. The id u64::MAX is
+// rejected, since no fresh ids could be handed out after it.
+pub fn read_fasta_records(reader: &mut dyn BufRead) -> io::Result<Vec<FastaRecord>> {
+    let mut records: Vec<FastaRecord> = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('>') {
+            let (id, birth_step) = parse_header(header);
+            if id == Some(u64::MAX) {
+                return Err(invalid_data(
+                    i + 1,
+                    "organism id leaves no ids for new organisms",
+                ));
+            }
+            records.push(FastaRecord {
+                id,
+                birth_step,
+                header: header.to_string(),
+                genes: BaseSeq::new(),
+            });
+        } else {
+            let record = records
+                .last_mut()
+                .ok_or_else(|| invalid_data(i + 1, "sequence before first header"))?;
+            record
+                .genes
+                .extend(parse_seq(line).map_err(|e| invalid_data(i + 1, e))?);
+        }
+    }
+    Ok(records)
+}
+
+/**
+ * Load a population, rebuilding each body with `build`. Organisms keep the id and birth
+ * step from their header when present, otherwise they get a fresh id and birth step 0.
+ */
+pub fn read_fasta<O>(
+    path: impl AsRef<Path>,
+    build: &dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+    rng: &mut ThreadRng,
+) -> io::Result<Vec<Organism<O>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let records = read_fasta_records(&mut reader)?;
+
+    if let Some(max_id) = records.iter().filter_map(|r| r.id).max() {
+        reserve_organism_ids(max_id);
+    }
+
+    Ok(records
+        .into_iter()
+        .map(|r| {
+            let body = build(&r.genes, rng);
+            let mut org = Organism::new(r.genes, body, r.birth_step.unwrap_or(0));
+            if let Some(id) = r.id {
+                org.id = id;
+            }
+            org
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evol_prim::Base::*;
+
+    #[test]
+    fn reads_back_ids_and_birth_steps() {
+        let text = ">7 birth=3 body=()\nACTG\nGA\n>other tool\nTT\n";
+        let records = read_fasta_records(&mut text.as_bytes()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].id, records[0].birth_step), (Some(7), Some(3)));
+        assert_eq!(records[0].genes, vec![A, C, T, G, G, A]);
+        assert_eq!((records[1].id, records[1].birth_step), (None, None));
+    }
+
+    #[test]
+    fn rejects_the_largest_id() {
+        let text = format!(">{} birth=0\nACTG\n", u64::MAX);
+        let err = read_fasta_records(&mut text.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn written_populations_read_back() {
+        let long = (0..130).map(|i| BASES[i % 4]).collect::<BaseSeq>();
+        let organisms = vec![
+            Organism::new(long, 1.5, 4),
+            Organism::new(vec![G, A], 0.0, 9),
+        ];
+        let mut text = Vec::new();
+        write_fasta_to(&mut text, &organisms).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        // 130 bases wrap onto lines of 60, 60 and 10
+        let lines = text.lines().map(|l| l.len()).collect::<Vec<usize>>();
+        assert_eq!(&lines[1..4], &[60, 60, 10]);
+
+        let records = read_fasta_records(&mut text.as_bytes()).unwrap();
+        assert_eq!(records.len(), 2);
+        for (r, o) in records.iter().zip(&organisms) {
+            assert_eq!((r.id, r.birth_step), (Some(o.id), Some(o.birth_step)));
+            assert_eq!(r.genes, o.genes);
+        }
+        assert!(records[0].header.ends_with("body=1.5"));
+    }
+}
@@ -0,0 +1,232 @@
+// Gene boundaries and dosage, so duplicated genes are expressed rather than ignored
+//
+// A gene is a start codon, a tag base naming which gene it is, a payload and a stop codon.
+// Genes may appear anywhere and any number of times; the copies of each gene are combined
+// by a dosage rule. A copy whose payload is not exactly payload_len bases long (e.g. after
+// an indel, or missing its stop codon) is non-functional and not expressed.
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::evol_prim::*;
+
+#[derive(Debug, Clone)]
+pub struct GeneSchema {
+    pub start: &'static [Base],
+    pub stop: &'static [Base],
+    pub payload_len: usize,
+    // Tags (as Base numbers) below this name genes; other tags are ignored
+    pub kinds: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneCopy {
+    pub kind: usize,
+    // Position of the start codon
+    pub locus: usize,
+    pub payload: BaseSeq,
+    pub functional: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dosage {
+    // Values of all functional copies added up
+    Sum,
+    // Largest value among functional copies
+    Max,
+    // Value of the functional copy nearest the start of the genome
+    FirstFunctional,
+}
+
+impl GeneSchema {
+    pub fn decode(&self, seq: &[Base]) -> Vec<GeneCopy> {
+        let mut copies = Vec::new();
+        let mut i = 0;
+        while i + self.start.len() < seq.len() {
+            if !seq[i..].starts_with(self.start) {
+                i += 1;
+                continue;
+            }
+            let kind = seq[i + self.start.len()] as usize;
+            if kind >= self.kinds {
+                i += 1;
+                continue;
+            }
+            let payload_start = i + self.start.len() + 1;
+            // A stop codon right after a full payload wins over an earlier one that happens
+            // to appear inside the payload
+            let in_frame = payload_start + self.payload_len;
+            let stop = if in_frame <= seq.len() && seq[in_frame..].starts_with(self.stop) {
+                Some(in_frame)
+            } else {
+                (payload_start..=seq.len().saturating_sub(self.stop.len()))
+                    .find(|j| seq[*j..].starts_with(self.stop))
+            };
+            let payload_end = stop.unwrap_or(seq.len());
+            let payload = seq[payload_start..payload_end].to_vec();
+            copies.push(GeneCopy {
+                kind,
+                locus: i,
+                functional: stop.is_some() && payload.len() == self.payload_len,
+                payload,
+            });
+            i = stop.map(|s| s + self.stop.len()).unwrap_or(seq.len());
+        }
+        copies
+    }
+
+    pub fn encode(&self, kind: usize, payload: &[Base]) -> BaseSeq {
+        let mut gene = self.start.to_vec();
+        gene.push(BASES[kind]);
+        gene.extend_from_slice(payload);
+        gene.extend_from_slice(self.stop);
+        gene
+    }
+}
+
+/**
+ * Combined value of the functional copies of gene `kind`, each valued by `value` from its
+ * payload. None if there is no functional copy.
+ */
+pub fn express(
+    copies: &[GeneCopy],
+    kind: usize,
+    dosage: Dosage,
+    value: &dyn Fn(&[Base]) -> f32,
+) -> Option<f32> {
+    let mut values = copies
+        .iter()
+        .filter(|c| c.kind == kind && c.functional)
+        .map(|c| value(&c.payload));
+    match dosage {
+        Dosage::Sum => values.reduce(|a, b| a + b),
+        Dosage::Max => values.reduce(f32::max),
+        Dosage::FirstFunctional => values.next(),
+    }
+}
+
+// Number of copies of each gene kind, functional or not
+pub fn copy_numbers(copies: &[GeneCopy], kinds: usize) -> Vec<usize> {
+    let mut counts = vec![0; kinds];
+    for c in copies {
+        counts[c.kind] += 1;
+    }
+    counts
+}
+
+// Tandem duplication: seq[start..start + len] is inserted again right after itself
+pub fn duplicate_segment(seq: &[Base], start: usize, len: usize) -> BaseSeq {
+    let start = start.min(seq.len());
+    let end = (start + len).min(seq.len());
+    [&seq[..end], &seq[start..end], &seq[end..]].concat()
+}
+
+/**
+ * clone_with_mutation, preceded with probability duplication_prob by the tandem duplication
+ * of a random segment of 1 to max_segment bases.
+ */
+pub fn clone_with_duplication(
+    seq: &BaseSeq,
+    rng: &mut ThreadRng,
+    duplication_prob: f32,
+    max_segment: usize,
+    insertion_prob: f32,
+    deletion_prob: f32,
+    base_change_prob: f32,
+) -> BaseSeq {
+    let duplicated = if !seq.is_empty() && rng.gen::<f32>() < duplication_prob {
+        let start = rng.gen_range(0..seq.len());
+        let len = rng.gen_range(1..=max_segment.max(1));
+        duplicate_segment(seq, start, len)
+    } else {
+        seq.clone()
+    };
+    clone_with_mutation(
+        &duplicated,
+        rng,
+        insertion_prob,
+        deletion_prob,
+        base_change_prob,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evol_prim::Base::*;
+
+    const SCHEMA: GeneSchema = GeneSchema {
+        start: &[T, A, T, A],
+        stop: &[G, G, G],
+        payload_len: 4,
+        kinds: 2,
+    };
+
+    #[test]
+    fn stop_codon_inside_payload_is_not_a_stop() {
+        for payload in [[G, G, G, A], [A, G, G, G], [G, G, G, G]] {
+            let seq = [vec![C, C], SCHEMA.encode(1, &payload), vec![C]].concat();
+            let copies = SCHEMA.decode(&seq);
+            assert_eq!(copies.len(), 1);
+            assert!(copies[0].functional);
+            assert_eq!(copies[0].kind, 1);
+            assert_eq!(copies[0].locus, 2);
+            assert_eq!(copies[0].payload, payload.to_vec());
+        }
+    }
+
+    #[test]
+    fn payload_of_wrong_length_is_not_functional() {
+        let mut seq = SCHEMA.encode(0, &[A, C, T, C]);
+        seq.remove(5);
+        let copies = SCHEMA.decode(&seq);
+        assert_eq!(copies.len(), 1);
+        assert!(!copies[0].functional);
+        assert_eq!(
+            express(&copies, 0, Dosage::Sum, &|p: &[Base]| p.len() as f32),
+            None
+        );
+    }
+
+    // Number of C bases in the payload
+    fn cs(payload: &[Base]) -> f32 {
+        payload.iter().filter(|b| **b == C).count() as f32
+    }
+
+    #[test]
+    fn duplicated_genes_are_combined_by_dosage() {
+        let mut broken = SCHEMA.encode(0, &[C, C, C, C]);
+        broken.remove(5);
+        let seq = [
+            broken,
+            SCHEMA.encode(0, &[C, A, A, A]),
+            vec![A, A],
+            SCHEMA.encode(0, &[C, C, C, A]),
+        ]
+        .concat();
+        let copies = SCHEMA.decode(&seq);
+        assert_eq!(copy_numbers(&copies, 2), vec![3, 0]);
+        assert_eq!(express(&copies, 0, Dosage::Sum, &cs), Some(4.0));
+        assert_eq!(express(&copies, 0, Dosage::Max, &cs), Some(3.0));
+        assert_eq!(express(&copies, 0, Dosage::FirstFunctional, &cs), Some(1.0));
+        assert_eq!(express(&copies, 1, Dosage::Sum, &cs), None);
+    }
+
+    #[test]
+    fn segments_are_duplicated_in_tandem() {
+        let seq = [A, C, T, G];
+        assert_eq!(duplicate_segment(&seq, 1, 2), vec![A, C, T, C, T, G]);
+        // Segments end at the end of the sequence
+        assert_eq!(duplicate_segment(&seq, 3, 5), vec![A, C, T, G, G]);
+        assert_eq!(duplicate_segment(&seq, 4, 2), seq.to_vec());
+
+        let mut rng = rand::thread_rng();
+        let seq = vec![A, C, T, G, G, T, C, A];
+        for _ in 0..20 {
+            let copy = clone_with_duplication(&seq, &mut rng, 1.0, 3, 0.0, 0.0, 0.0);
+            let len = copy.len() - seq.len();
+            assert!((1..=3).contains(&len));
+            assert!((0..seq.len()).any(|start| duplicate_segment(&seq, start, len) == copy));
+        }
+    }
+}
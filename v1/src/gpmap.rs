@@ -0,0 +1,332 @@
+// Genotype-phenotype map of short genomes
+//
+// Every genotype up to a length limit is built, and every distinct phenotype evaluated once,
+// so genotypes building the same phenotype (by key) share one fitness and are neutral to
+// each other; single mutations between them connect neutral networks.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use rand::prelude::ThreadRng;
+
+use crate::evol_prim::*;
+
+#[derive(Debug, Clone)]
+pub struct GenotypeEntry {
+    pub genes: BaseSeq,
+    pub phenotype: String,
+    pub fitness: f32,
+    // Index into GpMap::network_sizes
+    pub network: usize,
+    // Fraction of single mutations (within the enumerated lengths) keeping the phenotype
+    pub robustness: f32,
+    // No single mutation has strictly higher fitness
+    pub local_optimum: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct GpMap {
+    pub min_len: usize,
+    pub max_len: usize,
+    pub entries: Vec<GenotypeEntry>,
+    pub network_sizes: Vec<usize>,
+}
+
+// All sequences with lengths in [min_len, max_len], shortest first
+pub fn all_genotypes(min_len: usize, max_len: usize) -> Vec<BaseSeq> {
+    let mut genotypes = Vec::new();
+    let mut current: Vec<BaseSeq> = vec![Vec::new()];
+    for len in 0..=max_len {
+        if len >= min_len {
+            genotypes.extend(current.iter().cloned());
+        }
+        if len < max_len {
+            current = current
+                .iter()
+                .flat_map(|s| {
+                    BASES.iter().map(move |b| {
+                        let mut next = s.clone();
+                        next.push(*b);
+                        next
+                    })
+                })
+                .collect();
+        }
+    }
+    genotypes
+}
+
+// Every sequence one substitution, insertion or deletion away, without duplicates
+pub fn single_mutants(seq: &[Base]) -> Vec<BaseSeq> {
+    let mut mutants = Vec::new();
+    for i in 0..seq.len() {
+        for b in BASES.iter().filter(|b| **b != seq[i]) {
+            let mut m = seq.to_vec();
+            m[i] = *b;
+            mutants.push(m);
+        }
+        let mut m = seq.to_vec();
+        m.remove(i);
+        mutants.push(m);
+    }
+    for i in 0..=seq.len() {
+        for b in BASES {
+            let mut m = seq.to_vec();
+            m.insert(i, b);
+            mutants.push(m);
+        }
+    }
+    mutants.sort_by_key(|m| seq_to_string(m));
+    mutants.dedup();
+    mutants
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+impl GpMap {
+    /**
+     * Build and evaluate every genotype with lengths in [min_len, max_len]. `phenotype` keys
+     * the built body (e.g. its Debug string) and `fitness` gives its expected fitness. Only
+     * the first body built with each key is evaluated; fitness should be deterministic (e.g.
+     * against a fixed set of environments) for the comparisons between phenotypes to hold.
+     */
+    pub fn enumerate<O>(
+        min_len: usize,
+        max_len: usize,
+        build: &dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+        phenotype: &dyn Fn(&O) -> String,
+        fitness: &dyn Fn(&O, &mut ThreadRng) -> f32,
+        rng: &mut ThreadRng,
+    ) -> Self {
+        let genotypes = all_genotypes(min_len, max_len);
+        let mut fitness_by_phenotype = HashMap::new();
+        let evaluated = genotypes
+            .iter()
+            .map(|g| {
+                let body = build(g, rng);
+                let key = phenotype(&body);
+                let f = *fitness_by_phenotype
+                    .entry(key.clone())
+                    .or_insert_with(|| fitness(&body, rng));
+                (key, f)
+            })
+            .collect::<Vec<(String, f32)>>();
+        let index = genotypes
+            .iter()
+            .enumerate()
+            .map(|(i, g)| (g.clone(), i))
+            .collect::<HashMap<BaseSeq, usize>>();
+
+        let mut parent = (0..genotypes.len()).collect::<Vec<usize>>();
+        let mut robustness = Vec::with_capacity(genotypes.len());
+        let mut local_optimum = Vec::with_capacity(genotypes.len());
+        for (i, g) in genotypes.iter().enumerate() {
+            let neighbours = single_mutants(g)
+                .iter()
+                .filter_map(|m| index.get(m).copied())
+                .collect::<Vec<usize>>();
+            let mut neutral = 0;
+            for j in &neighbours {
+                if evaluated[*j].0 == evaluated[i].0 {
+                    neutral += 1;
+                    let (a, b) = (find(&mut parent, i), find(&mut parent, *j));
+                    parent[a] = b;
+                }
+            }
+            robustness.push(if neighbours.is_empty() {
+                0.0
+            } else {
+                neutral as f32 / neighbours.len() as f32
+            });
+            local_optimum.push(neighbours.iter().all(|j| evaluated[*j].1 <= evaluated[i].1));
+        }
+
+        let mut network_ids: HashMap<usize, usize> = HashMap::new();
+        let mut network_sizes = Vec::new();
+        let mut entries = Vec::with_capacity(genotypes.len());
+        for (i, ((genes, (phenotype, fitness)), (robustness, local_optimum))) in genotypes
+            .into_iter()
+            .zip(evaluated)
+            .zip(robustness.into_iter().zip(local_optimum))
+            .enumerate()
+        {
+            let root = find(&mut parent, i);
+            let network = *network_ids.entry(root).or_insert_with(|| {
+                network_sizes.push(0);
+                network_sizes.len() - 1
+            });
+            network_sizes[network] += 1;
+            entries.push(GenotypeEntry {
+                genes,
+                phenotype,
+                fitness,
+                network,
+                robustness,
+                local_optimum,
+            });
+        }
+
+        GpMap {
+            min_len,
+            max_len,
+            entries,
+            network_sizes,
+        }
+    }
+
+    pub fn local_optima(&self) -> Vec<&GenotypeEntry> {
+        self.entries.iter().filter(|e| e.local_optimum).collect()
+    }
+
+    pub fn mean_robustness(&self) -> f32 {
+        if self.entries.is_empty() {
+            return 0.0;
+        }
+        self.entries.iter().map(|e| e.robustness).sum::<f32>() / self.entries.len() as f32
+    }
+
+    // Number of distinct phenotypes
+    pub fn phenotype_count(&self) -> usize {
+        let mut phenotypes = self
+            .entries
+            .iter()
+            .map(|e| &e.phenotype)
+            .collect::<Vec<&String>>();
+        phenotypes.sort();
+        phenotypes.dedup();
+        phenotypes.len()
+    }
+
+    /**
+     * One row per genotype, tab separated, with a header row:
+     * genes, phenotype, fitness, network, network_size, robustness, local_optimum
+     */
+    pub fn write_tsv_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "genes\tphenotype\tfitness\tnetwork\tnetwork_size\trobustness\tlocal_optimum"
+        )?;
+        for e in &self.entries {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                seq_to_string(&e.genes),
+                e.phenotype,
+                e.fitness,
+                e.network,
+                self.network_sizes[e.network],
+                e.robustness,
+                e.local_optimum
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_tsv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_tsv_to(&mut writer)?;
+        writer.flush()
+    }
+}
+
+impl std::fmt::Display for GpMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "lengths {}..={}, genotypes {}, phenotypes {}, neutral networks {} (largest {}), mean robustness {:.3}, local optima {}",
+            self.min_len,
+            self.max_len,
+            self.entries.len(),
+            self.phenotype_count(),
+            self.network_sizes.len(),
+            self.network_sizes.iter().max().unwrap_or(&0),
+            self.mean_robustness(),
+            self.local_optima().len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::evol_prim::Base::*;
+
+    #[test]
+    fn genotypes_of_one_phenotype_share_a_fitness() {
+        let mut rng = rand::thread_rng();
+        // Phenotype: count of A; fitness is noisy, so only caching makes it agree
+        let map = GpMap::enumerate(
+            1,
+            3,
+            &|g: &BaseSeq, _: &mut ThreadRng| g.iter().filter(|b| **b == A).count(),
+            &|a: &usize| a.to_string(),
+            &|a: &usize, rng: &mut ThreadRng| *a as f32 + rng.gen::<f32>(),
+            &mut rng,
+        );
+        assert_eq!(map.entries.len(), 4 + 16 + 64);
+        assert_eq!(map.phenotype_count(), 4);
+        for a in &map.entries {
+            for b in map.entries.iter().filter(|b| b.phenotype == a.phenotype) {
+                assert_eq!(a.fitness, b.fitness);
+            }
+        }
+    }
+
+    #[test]
+    fn single_mutants_are_distinct() {
+        let mutants = single_mutants(&[A, A, C]);
+        let mut unique = mutants.clone();
+        unique.dedup();
+        assert_eq!(mutants.len(), unique.len());
+        assert!(mutants.contains(&vec![A, C]));
+        assert!(!mutants.contains(&vec![A, A, C]));
+    }
+
+    #[test]
+    fn networks_robustness_and_optima_of_a_tiny_landscape() {
+        let mut rng = rand::thread_rng();
+        // Phenotype and fitness: count of A, over the 4 + 16 genotypes of lengths 1 and 2
+        let map = GpMap::enumerate(
+            1,
+            2,
+            &|g: &BaseSeq, _: &mut ThreadRng| g.iter().filter(|b| **b == A).count(),
+            &|a: &usize| a.to_string(),
+            &|a: &usize, _: &mut ThreadRng| *a as f32,
+            &mut rng,
+        );
+        let entry = |s: &str| {
+            let genes = parse_seq(s).unwrap();
+            map.entries.iter().find(|e| e.genes == genes).unwrap()
+        };
+
+        // No A: 3 + 9 genotypes, one A: A and 6 of length 2, two A: AA alone
+        let mut sizes = map.network_sizes.clone();
+        sizes.sort();
+        assert_eq!(sizes, vec![1, 7, 12]);
+        assert_eq!(map.network_sizes[entry("CA").network], 7);
+        assert_eq!(entry("A").network, entry("GA").network);
+
+        // A: 3 substitutions and 7 distinct insertions, 6 of them keeping one A
+        assert_eq!(entry("A").robustness, 0.6);
+        // C: T, G, CC, TC, GC, CT and CG of its 10 neighbours keep no A
+        assert_eq!(entry("C").robustness, 0.7);
+        // AA: 6 substitutions and the deletion to A, none keeping both
+        assert_eq!(entry("AA").robustness, 0.0);
+        // AC: AT, AG and A of its 6 substitutions and 2 deletions keep one A
+        assert_eq!(entry("AC").robustness, 0.375);
+
+        let optima = map.local_optima();
+        assert_eq!(optima.len(), 1);
+        assert_eq!(optima[0].genes, vec![A, A]);
+    }
+}
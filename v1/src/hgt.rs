@@ -0,0 +1,186 @@
+// Horizontal gene transfer: organisms copy a segment of another organism's genome into
+// their own
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::evol_prim::*;
+
+#[derive(Debug, Copy, Clone)]
+pub enum DonorSelection {
+    Random,
+    // Donor is within this many positions of the recipient in the population. Position in
+    // the population is the only notion of locality the simulation has.
+    Nearby(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferEvent {
+    pub t: i32,
+    pub donor: u64,
+    pub recipient: u64,
+    // Start of the copied segment in the donor genome
+    pub donor_start: usize,
+    // Position in the recipient genome the segment was inserted before
+    pub inserted_at: usize,
+    pub segment: BaseSeq,
+}
+
+#[derive(Debug, Clone)]
+pub struct HorizontalTransfer {
+    // Chance per organism per step of receiving a segment
+    pub probability: f32,
+    pub min_segment: usize,
+    pub max_segment: usize,
+    pub donor: DonorSelection,
+    pub log: Vec<TransferEvent>,
+}
+
+impl HorizontalTransfer {
+    pub fn new(
+        probability: f32,
+        min_segment: usize,
+        max_segment: usize,
+        donor: DonorSelection,
+    ) -> Self {
+        HorizontalTransfer {
+            probability,
+            min_segment,
+            max_segment,
+            donor,
+            log: Vec::new(),
+        }
+    }
+
+    fn choose_donor(&self, recipient: usize, population: usize, rng: &mut ThreadRng) -> usize {
+        match self.donor {
+            DonorSelection::Random => (recipient + rng.gen_range(1..population)) % population,
+            DonorSelection::Nearby(radius) => {
+                let radius = radius.max(1);
+                let low = recipient.saturating_sub(radius);
+                let high = (recipient + radius).min(population - 1);
+                // Skip over the recipient itself
+                let i = rng.gen_range(low..high);
+                if i >= recipient {
+                    i + 1
+                } else {
+                    i
+                }
+            }
+        }
+    }
+
+    /**
+     * Run one transfer phase. Donor segments are taken from the genomes as they were at the
+     * start of the phase, so a segment received this step is not passed on until the next.
+     * Each recipient's body is rebuilt from its new genome with build, so the transfer takes
+     * effect at once rather than only in its offspring.
+     */
+    pub fn transfer<O>(
+        &mut self,
+        organisms: &mut [Organism<O>],
+        build: &dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+        t: i32,
+        rng: &mut ThreadRng,
+    ) {
+        if organisms.len() < 2 || self.max_segment == 0 {
+            return;
+        }
+        let donors = organisms
+            .iter()
+            .map(|o| (o.id, o.genes.clone()))
+            .collect::<Vec<(u64, BaseSeq)>>();
+
+        for (recipient, org) in organisms.iter_mut().enumerate() {
+            if rng.gen::<f32>() >= self.probability {
+                continue;
+            }
+            let (donor_id, donor_genes) = &donors[self.choose_donor(recipient, donors.len(), rng)];
+            if donor_genes.is_empty() {
+                continue;
+            }
+
+            let max = self.max_segment.max(self.min_segment);
+            let len = rng
+                .gen_range(self.min_segment.clamp(1, max)..=max)
+                .min(donor_genes.len());
+            let donor_start = rng.gen_range(0..=donor_genes.len() - len);
+            let segment = donor_genes[donor_start..donor_start + len].to_vec();
+            let inserted_at = rng.gen_range(0..=org.genes.len());
+            org.genes
+                .splice(inserted_at..inserted_at, segment.iter().copied());
+            org.body = build(&org.genes, rng);
+
+            self.log.push(TransferEvent {
+                t,
+                donor: *donor_id,
+                recipient: org.id,
+                donor_start,
+                inserted_at,
+                segment,
+            });
+        }
+    }
+
+    pub fn transfers_at(&self, t: i32) -> usize {
+        self.log.iter().filter(|e| e.t == t).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genome_length(genes: &BaseSeq, _: &mut ThreadRng) -> usize {
+        genes.len()
+    }
+
+    fn population(n: usize) -> Vec<Organism<usize>> {
+        (0..n)
+            .map(|i| {
+                let genes = vec![[Base::A, Base::C, Base::T, Base::G][i % 4]; 10];
+                Organism::new(genes, 10, 0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recipients_get_a_donor_segment_and_a_rebuilt_body() {
+        let mut rng = rand::thread_rng();
+        let mut orgs = population(4);
+        let before = orgs.clone();
+        let mut hgt = HorizontalTransfer::new(1.0, 2, 5, DonorSelection::Random);
+        hgt.transfer(&mut orgs, &genome_length, 7, &mut rng);
+
+        assert_eq!(hgt.transfers_at(7), 4);
+        for e in &hgt.log {
+            assert_ne!(e.donor, e.recipient);
+            assert!((2..=5).contains(&e.segment.len()));
+            let donor = before.iter().find(|o| o.id == e.donor).unwrap();
+            assert_eq!(
+                donor.genes[e.donor_start..e.donor_start + e.segment.len()],
+                e.segment[..]
+            );
+            let org = orgs.iter().find(|o| o.id == e.recipient).unwrap();
+            assert_eq!(org.genes.len(), 10 + e.segment.len());
+            assert_eq!(
+                org.genes[e.inserted_at..e.inserted_at + e.segment.len()],
+                e.segment[..]
+            );
+            assert_eq!(org.body, org.genes.len());
+        }
+    }
+
+    #[test]
+    fn nearby_donors_are_within_the_radius() {
+        let mut rng = rand::thread_rng();
+        let mut orgs = population(20);
+        let ids = orgs.iter().map(|o| o.id).collect::<Vec<u64>>();
+        let mut hgt = HorizontalTransfer::new(1.0, 1, 1, DonorSelection::Nearby(2));
+        hgt.transfer(&mut orgs, &genome_length, 0, &mut rng);
+        for e in &hgt.log {
+            let position = |id| ids.iter().position(|i| *i == id).unwrap();
+            assert!(position(e.donor).abs_diff(position(e.recipient)) <= 2);
+        }
+    }
+}
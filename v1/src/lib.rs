@@ -0,0 +1,33 @@
+// Experiments and the building blocks they share; main.rs runs one of them
+
+pub mod brain;
+pub mod chem;
+pub mod diploid;
+pub mod diversity;
+pub mod e0;
+pub mod e1;
+pub mod e10;
+pub mod e11;
+pub mod e12;
+pub mod e13;
+pub mod e2;
+pub mod e3;
+pub mod e4;
+pub mod e5;
+pub mod e6;
+pub mod e7;
+pub mod e8;
+pub mod e9;
+pub mod elite;
+pub mod evol_prim;
+pub mod fasta;
+pub mod genes;
+pub mod gpmap;
+pub mod hgt;
+pub mod mutscan;
+pub mod sim;
+pub mod soup;
+pub mod species;
+pub mod tracker;
+pub mod transposon;
+pub mod vis;
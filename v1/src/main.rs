@@ -1,25 +1,14 @@
 extern crate rand;
 
-mod e0;
-mod e1;
-mod e10;
-mod e2;
-mod e3;
-mod e4;
-mod e5;
-mod e6;
-mod e7;
-mod e8;
-mod e9;
-mod evol_prim;
-mod sim;
-mod vis;
-
-use evol_prim::Base::*;
-use evol_prim::*;
+use evolution::diversity::{hamming, DiversityStats};
+use evolution::e10;
+use evolution::evol_prim::Base::*;
+use evolution::evol_prim::*;
+use evolution::sim::Simulation;
+use evolution::species::Speciation;
+use evolution::tracker::{Marker, MarkerTracker};
+use evolution::vis::create_1d_sim_image;
 use rand::Rng;
-use sim::Simulation;
-use vis::create_1d_sim_image;
 
 fn main() {
     // let s = vec![T, T, C, T];
@@ -32,11 +21,16 @@ fn main() {
 
     let mut population = Vec::new();
     for _ in 0..100 {
-        let seq = (0..8).map(|_| rng.gen::<Base>()).collect::<Vec<Base>>();
+        let seq = (0..(2 + e10::LEARNING_RULE_INPUTS) * 4)
+            .map(|_| rng.gen::<Base>())
+            .collect::<Vec<Base>>();
         let body = e10::build(&seq, &mut rng); // byteToFeatureSpace(38) = 0.3; byteToFeatureSpace(26) = 0.2
-        population.push(Organism { genes: seq, body });
+        population.push(Organism::new(seq, body, 0));
     }
 
+    let mut tracker = MarkerTracker::new(0.95);
+    tracker.register("learns", Marker::Predicate(&e10::learns));
+
     let mut sim = Simulation {
         R: &e10::reproduce,
         D: &e10::death,
@@ -52,12 +46,20 @@ fn main() {
         t: 0,
         max_t: 300,
         rng,
+        speciation: Some(Speciation::new(2.0, |a, b| hamming(a, b) as f32, false)),
+        hgt: None,
+        tracker: Some(tracker),
+        elitism: None,
+        hall_of_fame: None,
+        soup: None,
     };
 
+    // Only the organisms and environment of each step are kept for display, not the
+    // speciation and tracker history, which grows with every step
     let mut sim_hist_for_display = Vec::new();
     let mut last_5_fit_sum = 0;
     while sim.t < sim.max_t {
-        sim_hist_for_display.push(sim.clone());
+        sim_hist_for_display.push((sim.organisms.clone(), sim.environment.clone()));
 
         if sim.t % 1 == 0 {
             //println!("{:?}", sim.E);
@@ -139,15 +141,22 @@ fn main() {
                 sim.environment.safe_zone_low,
                 sim.environment.safe_zone_high
             );
+            println!("{}", DiversityStats::compute(&sim.organisms, &mut sim.rng));
+            if let Some(census) = sim.speciation.as_ref().and_then(|s| s.history.last()) {
+                println!("{}", census);
+            }
         }
         sim.run_step();
     }
 
     println!("Last 5 fit sum: {}", last_5_fit_sum);
+    if let Some(tracker) = &sim.tracker {
+        println!("{}", tracker);
+    }
 
     let (min, max) = sim_hist_for_display
         .iter()
-        .map(|s| &s.organisms)
+        .map(|(organisms, _)| organisms)
         .map(|o| {
             (
                 o.iter()
@@ -192,7 +201,7 @@ fn main() {
 
     sim_hist_for_display
         .iter_mut()
-        .map(|s| &mut s.organisms)
+        .map(|(organisms, _)| organisms)
         .for_each(|o| {
             o.iter_mut().for_each(|o| {
                 let mut pos = (o.body.position + 1.0) / 2.0;
@@ -210,11 +219,11 @@ fn main() {
     create_1d_sim_image(
         400,
         &sim_hist_for_display,
-        |s| Box::new(s.organisms.iter().map(|o| o.body.position)),
-        |s| {
+        |(organisms, _)| Box::new(organisms.iter().map(|o| o.body.position)),
+        |(_, environment)| {
             (
-                (s.environment.safe_zone_low + 1.0) / 2.0,
-                (s.environment.safe_zone_high + 1.0) / 2.0,
+                (environment.safe_zone_low + 1.0) / 2.0,
+                (environment.safe_zone_high + 1.0) / 2.0,
             )
         },
     );
@@ -0,0 +1,306 @@
+// Mutational scanning: the fitness effect of every single-base mutation of a genome
+//
+// Each mutant is rebuilt with the experiment's build function and evaluated against the
+// same set of environments as the unmutated genome, so effects are paired comparisons.
+
+use std::collections::HashSet;
+
+use rand::prelude::ThreadRng;
+use rand::seq::SliceRandom;
+
+use crate::evol_prim::*;
+
+// Effects smaller than this in magnitude count as neutral
+pub const NEUTRAL_EPSILON: f32 = 1e-6;
+
+// Named body trait, e.g. ("learning_factor", |b| b.learning_factor)
+pub type Trait<'a, O> = (&'a str, &'a dyn Fn(&O) -> f32);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MutationKind {
+    Substitution(Base),
+    // Inserted before the locus; a locus equal to the genome length appends
+    Insertion(Base),
+    Deletion,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mutation {
+    pub locus: usize,
+    pub kind: MutationKind,
+}
+
+impl Mutation {
+    /**
+     * Every single substitution, insertion and deletion of seq giving a distinct mutant, so
+     * e.g. deleting either base of a run is counted once, at the first of them.
+     */
+    pub fn all(seq: &[Base]) -> Vec<Mutation> {
+        let mut candidates = Vec::new();
+        for locus in 0..=seq.len() {
+            if locus < seq.len() {
+                for b in BASES.iter().filter(|b| **b != seq[locus]) {
+                    candidates.push(Mutation {
+                        locus,
+                        kind: MutationKind::Substitution(*b),
+                    });
+                }
+                candidates.push(Mutation {
+                    locus,
+                    kind: MutationKind::Deletion,
+                });
+            }
+            for b in BASES {
+                candidates.push(Mutation {
+                    locus,
+                    kind: MutationKind::Insertion(b),
+                });
+            }
+        }
+        let mut seen = HashSet::new();
+        candidates
+            .into_iter()
+            .filter(|m| seen.insert(m.apply(seq)))
+            .collect()
+    }
+
+    pub fn apply(&self, seq: &[Base]) -> BaseSeq {
+        let mut mutant = seq.to_vec();
+        match self.kind {
+            MutationKind::Substitution(b) => mutant[self.locus] = b,
+            MutationKind::Insertion(b) => mutant.insert(self.locus, b),
+            MutationKind::Deletion => {
+                mutant.remove(self.locus);
+            }
+        }
+        mutant
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MutationEffect {
+    pub id: u64,
+    pub mutation: Mutation,
+    pub wild_fitness: f32,
+    pub mutant_fitness: f32,
+}
+
+impl MutationEffect {
+    pub fn delta(&self) -> f32 {
+        self.mutant_fitness - self.wild_fitness
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistogramBin {
+    pub low: f32,
+    pub high: f32,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MutationScan {
+    pub effects: Vec<MutationEffect>,
+}
+
+/**
+ * Scan every organism, or a random sample of `sample` of them, applying each single
+ * mutation and evaluating wild type and mutant with `evaluate` against `envs`.
+ */
+pub fn scan<O, E>(
+    organisms: &[Organism<O>],
+    sample: Option<usize>,
+    build: &dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+    evaluate: &dyn Fn(&O, &[E], &mut ThreadRng) -> f32,
+    envs: &[E],
+    rng: &mut ThreadRng,
+) -> MutationScan {
+    let scanned = match sample {
+        Some(n) => organisms
+            .choose_multiple(rng, n)
+            .collect::<Vec<&Organism<O>>>(),
+        None => organisms.iter().collect(),
+    };
+    let mut effects = Vec::new();
+    for org in scanned {
+        let wild = build(&org.genes, rng);
+        let wild_fitness = evaluate(&wild, envs, rng);
+        for mutation in Mutation::all(&org.genes) {
+            let mutant = build(&mutation.apply(&org.genes), rng);
+            effects.push(MutationEffect {
+                id: org.id,
+                mutation,
+                wild_fitness,
+                mutant_fitness: evaluate(&mutant, envs, rng),
+            });
+        }
+    }
+    MutationScan { effects }
+}
+
+impl MutationScan {
+    // Distribution of fitness effects over `bins` equal bins spanning the observed effects
+    pub fn dfe(&self, bins: usize) -> Vec<HistogramBin> {
+        let deltas = self.effects.iter().map(|e| e.delta()).collect::<Vec<f32>>();
+        if deltas.is_empty() || bins == 0 {
+            return Vec::new();
+        }
+        let low = deltas.iter().copied().fold(f32::INFINITY, f32::min);
+        let high = deltas.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let width = ((high - low) / bins as f32).max(f32::EPSILON);
+        let mut histogram = (0..bins)
+            .map(|i| HistogramBin {
+                low: low + width * i as f32,
+                high: low + width * (i + 1) as f32,
+                count: 0,
+            })
+            .collect::<Vec<HistogramBin>>();
+        for d in deltas {
+            let i = (((d - low) / width) as usize).min(bins - 1);
+            histogram[i].count += 1;
+        }
+        histogram
+    }
+
+    // Fractions of (deleterious, neutral, beneficial) mutations
+    pub fn effect_classes(&self) -> (f32, f32, f32) {
+        let n = self.effects.len().max(1) as f32;
+        let count = |f: &dyn Fn(f32) -> bool| {
+            self.effects.iter().filter(|e| f(e.delta())).count() as f32 / n
+        };
+        (
+            count(&|d| d < -NEUTRAL_EPSILON),
+            count(&|d| d.abs() <= NEUTRAL_EPSILON),
+            count(&|d| d > NEUTRAL_EPSILON),
+        )
+    }
+
+    /**
+     * Mean absolute fitness effect of substitutions and deletions at each locus, up to the
+     * longest scanned genome. Insertions are left out since they sit between loci.
+     */
+    pub fn locus_sensitivity(&self) -> Vec<f32> {
+        let mut totals: Vec<(f32, usize)> = Vec::new();
+        for e in &self.effects {
+            if let MutationKind::Insertion(_) = e.mutation.kind {
+                continue;
+            }
+            if totals.len() <= e.mutation.locus {
+                totals.resize(e.mutation.locus + 1, (0.0, 0));
+            }
+            totals[e.mutation.locus].0 += e.delta().abs();
+            totals[e.mutation.locus].1 += 1;
+        }
+        totals
+            .iter()
+            .map(|(sum, n)| if *n == 0 { 0.0 } else { sum / *n as f32 })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for MutationScan {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (deleterious, neutral, beneficial) = self.effect_classes();
+        let mean =
+            self.effects.iter().map(|e| e.delta()).sum::<f32>() / self.effects.len().max(1) as f32;
+        writeln!(
+            f,
+            "mutations {}, deleterious {:.3}, neutral {:.3}, beneficial {:.3}, mean effect {:.4}",
+            self.effects.len(),
+            deleterious,
+            neutral,
+            beneficial,
+            mean
+        )?;
+        for bin in self.dfe(10) {
+            writeln!(f, "  [{:8.3}, {:8.3}) {}", bin.low, bin.high, bin.count)?;
+        }
+        write!(f, "locus sensitivity [")?;
+        for (i, s) in self.locus_sensitivity().iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:.3}", s)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/**
+ * Knockout importance of each base for each named body trait: the mean absolute change in
+ * the trait when the base is substituted by each of the other three, over the given
+ * organisms. Substituting rather than deleting keeps the loci after it in place, so only the
+ * base itself is knocked out. Row i is locus i, with one column per trait in the order given.
+ */
+pub fn knockout_importance<O>(
+    organisms: &[Organism<O>],
+    build: &dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+    traits: &[Trait<O>],
+    rng: &mut ThreadRng,
+) -> Vec<Vec<f32>> {
+    let mut totals: Vec<(Vec<f32>, usize)> = Vec::new();
+    for org in organisms {
+        let wild = build(&org.genes, rng);
+        for locus in 0..org.genes.len() {
+            if totals.len() <= locus {
+                totals.resize(locus + 1, (vec![0.0; traits.len()], 0));
+            }
+            for b in BASES.iter().filter(|b| **b != org.genes[locus]) {
+                let knockout = Mutation {
+                    locus,
+                    kind: MutationKind::Substitution(*b),
+                };
+                let mutant = build(&knockout.apply(&org.genes), rng);
+                for (k, (_, t)) in traits.iter().enumerate() {
+                    totals[locus].0[k] += (t(&mutant) - t(&wild)).abs();
+                }
+                totals[locus].1 += 1;
+            }
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(sums, n)| sums.into_iter().map(|s| s / n.max(1) as f32).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evol_prim::Base::*;
+
+    #[test]
+    fn mutations_give_distinct_mutants() {
+        let seq = [A, A, C];
+        let mutants = Mutation::all(&seq)
+            .iter()
+            .map(|m| m.apply(&seq))
+            .collect::<Vec<BaseSeq>>();
+        let distinct = mutants.iter().collect::<HashSet<&BaseSeq>>();
+        assert_eq!(distinct.len(), mutants.len());
+        assert!(!mutants.contains(&seq.to_vec()));
+        // Deleting either A of the run gives AC, counted at the first
+        assert!(Mutation::all(&seq).contains(&Mutation {
+            locus: 0,
+            kind: MutationKind::Deletion
+        }));
+        assert!(!Mutation::all(&seq).contains(&Mutation {
+            locus: 1,
+            kind: MutationKind::Deletion
+        }));
+        assert_eq!(mutants.len(), crate::gpmap::single_mutants(&seq).len());
+    }
+
+    #[test]
+    fn knockout_importance_is_local() {
+        let mut rng = rand::thread_rng();
+        // Trait read from the second base only; a deletion would shift the third into place
+        let organisms = vec![Organism::new(vec![A, C, G], 0.0, 0)];
+        let build = |g: &BaseSeq, _: &mut ThreadRng| g[1] as usize as f32;
+        let second: Trait<f32> = ("second", &|b: &f32| *b);
+        let importance = knockout_importance(&organisms, &build, &[second], &mut rng);
+        assert_eq!(importance[0], vec![0.0]);
+        assert!(importance[1][0] > 0.0);
+        assert_eq!(importance[2], vec![0.0]);
+    }
+}
@@ -1,6 +1,13 @@
+use std::io;
+
 use rand::prelude::{SliceRandom, ThreadRng};
 
+use crate::elite::{Elitism, HallOfFame};
 use crate::evol_prim::*;
+use crate::hgt::HorizontalTransfer;
+use crate::soup::Soup;
+use crate::species::Speciation;
+use crate::tracker::MarkerTracker;
 
 pub struct Simulation<'a, O, E> {
     // Reproduce
@@ -21,34 +28,64 @@ pub struct Simulation<'a, O, E> {
     pub t: i32,
     pub max_t: i32,
     pub rng: ThreadRng,
+    // Optional species tracking (and fitness sharing) by genetic distance
+    pub speciation: Option<Speciation>,
+    // Optional horizontal gene transfer phase, run after reproduction
+    pub hgt: Option<HorizontalTransfer>,
+    // Optional per-step frequency tracking of genome motifs/traits
+    pub tracker: Option<MarkerTracker<'a>>,
+    // Optional protection of the best organisms from death and from the population cap
+    pub elitism: Option<Elitism<'a, O, E>>,
+    // Optional archive of the best genotypes seen over the whole run
+    pub hall_of_fame: Option<HallOfFame<'a, O, E>>,
+    // Optional primordial soup influx of random sequences
+    pub soup: Option<Soup>,
 }
 
 impl<'a, O: std::fmt::Debug + Clone, E: Environment> Simulation<'a, O, E> {
-    pub fn run(&mut self, print_freq: Option<u32>) {
-        while self.t < self.max_t {
+    // Run to max_t (or until the soup hands off); fails only if saving the hall of fame fails
+    pub fn run(&mut self, print_freq: Option<u32>) -> io::Result<()> {
+        while self.t < self.max_t && !self.soup.as_ref().is_some_and(|s| s.handed_off()) {
             self.run_step();
             if print_freq.map_or(false, |f| self.t % f as i32 == 0) {
-                println!("{:?}", (&self.organisms).into_iter().map(|o| &o.genes))
+                println!(
+                    "{:?}",
+                    self.organisms
+                        .iter()
+                        .map(|o| seq_to_string(&o.genes))
+                        .collect::<Vec<String>>()
+                )
             }
         }
+        if let Some(tracker) = &self.tracker {
+            println!("{}", tracker);
+        }
+        if let Some(hof) = &self.hall_of_fame {
+            if let Some(path) = &hof.save_path {
+                hof.save(path)?;
+            }
+        }
+        Ok(())
     }
 
     pub fn run_step(&mut self) {
         let mut new_organisms = Vec::new();
         let mut all_children = Vec::new();
+        let elites = self
+            .elitism
+            .as_ref()
+            .map(|e| e.elite_ids(&self.organisms, &self.environment))
+            .unwrap_or_default();
         while let Some(org) = self.organisms.pop() {
-            // Die?
-            if !(self.D)(&org, &self.environment, &mut self.rng) {
+            // Die? (elites always survive)
+            if elites.contains(&org.id) || !(self.D)(&org, &self.environment, &mut self.rng) {
                 // Reproduce
                 let babies = (self.R)(&org, &self.environment, &mut self.rng)
                     .into_iter()
                     .filter(|s| s.len() > 0)
                     .map(|s| {
                         let child_body = (self.B)(&s, &mut self.rng);
-                        Organism {
-                            genes: s,
-                            body: child_body,
-                        }
+                        Organism::new(s, child_body, self.t)
                     });
                 all_children.extend(babies);
 
@@ -57,23 +94,60 @@ impl<'a, O: std::fmt::Debug + Clone, E: Environment> Simulation<'a, O, E> {
             }
         }
 
+        if let Some(soup) = &self.soup {
+            all_children.extend(soup.inject(self.B, self.t, &mut self.rng));
+        }
+
         // Limit addition of children so that we don't sample between chidren and parents below
         // NOTE: Using the sampling below produces extreme genetic swings
         if new_organisms.len() < self.max_sequences {
             let current_size = new_organisms.len();
-            new_organisms.extend(&mut all_children.into_iter().take(self.max_sequences - current_size));
+            let space = self.max_sequences - current_size;
+            match &self.speciation {
+                Some(speciation) if speciation.fitness_sharing && all_children.len() > space => {
+                    // Children from small species are more likely to find room
+                    let admitted = all_children
+                        .choose_multiple_weighted(&mut self.rng, space, |o| {
+                            speciation.shared_weight(o)
+                        })
+                        .unwrap()
+                        .cloned()
+                        .collect::<Vec<Organism<O>>>();
+                    new_organisms.extend(admitted);
+                }
+                _ => new_organisms.extend(&mut all_children.into_iter().take(space)),
+            }
         }
 
         //self.organisms.clear(); // Should already be empty
         if new_organisms.len() > self.max_sequences {
+            // Elites are always kept, the rest of the space is sampled from everyone else
+            let (mut kept, others): (Vec<Organism<O>>, Vec<Organism<O>>) = new_organisms
+                .into_iter()
+                .partition(|o| elites.contains(&o.id));
+            kept.truncate(self.max_sequences);
+            self.organisms.append(&mut kept);
             // TODO: Do this without copying
-            new_organisms
-                .choose_multiple(&mut self.rng, self.max_sequences)
+            others
+                .choose_multiple(&mut self.rng, self.max_sequences - self.organisms.len())
                 .for_each(|o| self.organisms.push(o.clone()));
         } else {
             self.organisms.append(&mut new_organisms);
         }
 
+        // Only organisms that found room are assigned, so no species is born just to die
+        if let Some(speciation) = &mut self.speciation {
+            speciation.assign(self.organisms.iter(), self.t);
+        }
+
+        if let Some(soup) = &mut self.soup {
+            soup.detect(&self.organisms, self.t);
+        }
+
+        if let Some(hgt) = &mut self.hgt {
+            hgt.transfer(&mut self.organisms, self.B, self.t, &mut self.rng);
+        }
+
         // Update env and orgs for next cycle
 
         // Env must tick before org updates, otherwise organisms always appear out phase with env after each step
@@ -86,6 +160,16 @@ impl<'a, O: std::fmt::Debug + Clone, E: Environment> Simulation<'a, O, E> {
             (self.U)(org, &self.environment, &mut self.rng);
         }
 
+        if let Some(speciation) = &mut self.speciation {
+            speciation.census(&self.organisms, self.t, &mut self.rng);
+        }
+        if let Some(tracker) = &mut self.tracker {
+            tracker.record(&self.organisms, self.t);
+        }
+        if let Some(hof) = &mut self.hall_of_fame {
+            hof.consider(&self.organisms, &self.environment, self.t);
+        }
+
         self.t += 1;
     }
 }
@@ -104,6 +188,12 @@ impl <'a, O, E> Clone for Simulation<'a, O, E> where E: Clone, O: Clone {
             t: self.t,
             max_t: self.max_t,
             rng: self.rng.clone(),
+            speciation: self.speciation.clone(),
+            hgt: self.hgt.clone(),
+            tracker: self.tracker.clone(),
+            elitism: self.elitism.clone(),
+            hall_of_fame: self.hall_of_fame.clone(),
+            soup: self.soup.clone(),
         }
     }
 }
@@ -0,0 +1,124 @@
+// Primordial soup mode: random sequences flow into the population every step, and the first
+// appearance of a self-replicating motif is reported
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::evol_prim::*;
+
+#[derive(Debug, Clone)]
+pub struct ReplicatorDetection {
+    pub t: i32,
+    pub id: u64,
+    pub genes: BaseSeq,
+}
+
+#[derive(Debug, Clone)]
+pub struct Soup {
+    // Random sequences injected per step, subject to the population cap like any child
+    pub influx: usize,
+    // Injected sequence lengths are uniform in [min_len, max_len]
+    pub min_len: usize,
+    pub max_len: usize,
+    // Prefix that confers true reproduction, e.g. E1_REPRODUCE_PREFIX
+    pub replicator: &'static [Base],
+    // Stop Simulation::run at the first detection so the soup can be handed to a
+    // replicating experiment
+    pub hand_off: bool,
+    pub detection: Option<ReplicatorDetection>,
+}
+
+impl Soup {
+    pub fn random_seq(&self, rng: &mut ThreadRng) -> BaseSeq {
+        let len = rng.gen_range(self.min_len.max(1)..=self.max_len.max(self.min_len).max(1));
+        (0..len).map(|_| rng.gen()).collect()
+    }
+
+    pub fn inject<O>(
+        &self,
+        build: &dyn Fn(&BaseSeq, &mut ThreadRng) -> O,
+        t: i32,
+        rng: &mut ThreadRng,
+    ) -> Vec<Organism<O>> {
+        (0..self.influx)
+            .map(|_| {
+                let genes = self.random_seq(rng);
+                let body = build(&genes, rng);
+                Organism::new(genes, body, t)
+            })
+            .collect()
+    }
+
+    // Record (and report) the first organism carrying the replicator motif
+    pub fn detect<O>(&mut self, organisms: &[Organism<O>], t: i32) {
+        if self.detection.is_some() {
+            return;
+        }
+        if let Some(org) = organisms
+            .iter()
+            .find(|o| o.genes.starts_with(self.replicator))
+        {
+            println!(
+                "Replicator {} first appeared at step {} in organism {}: {}",
+                seq_to_string(self.replicator),
+                t,
+                org.id,
+                seq_to_string(&org.genes)
+            );
+            self.detection = Some(ReplicatorDetection {
+                t,
+                id: org.id,
+                genes: org.genes.clone(),
+            });
+        }
+    }
+
+    pub fn handed_off(&self) -> bool {
+        self.hand_off && self.detection.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evol_prim::Base::*;
+
+    fn soup() -> Soup {
+        Soup {
+            influx: 30,
+            min_len: 2,
+            max_len: 5,
+            replicator: &[A, T],
+            hand_off: false,
+            detection: None,
+        }
+    }
+
+    #[test]
+    fn inject_builds_influx_organisms_of_the_given_lengths() {
+        let mut rng = rand::thread_rng();
+        let injected = soup().inject(&|s: &BaseSeq, _: &mut ThreadRng| s.len(), 7, &mut rng);
+        assert_eq!(injected.len(), 30);
+        for o in &injected {
+            assert!((2..=5).contains(&o.genes.len()));
+            assert_eq!((o.body, o.birth_step), (o.genes.len(), 7));
+        }
+    }
+
+    #[test]
+    fn only_the_first_replicator_is_recorded() {
+        let mut soup = soup();
+        let organisms = [vec![C, C], vec![A, T, G], vec![A, T]]
+            .into_iter()
+            .map(|s| Organism::new(s, (), 0))
+            .collect::<Vec<Organism<()>>>();
+        soup.detect(&organisms[..1], 3);
+        assert!(soup.detection.is_none());
+        soup.detect(&organisms, 4);
+        soup.detect(&organisms[2..], 5);
+        let detection = soup.detection.as_ref().unwrap();
+        assert_eq!((detection.t, detection.id), (4, organisms[1].id));
+        assert_eq!(detection.genes, vec![A, T, G]);
+        assert!(!soup.handed_off());
+    }
+}
@@ -0,0 +1,307 @@
+// Speciation by genetic-distance clustering
+
+use std::collections::HashMap;
+use std::fmt;
+
+use rand::prelude::{SliceRandom, ThreadRng};
+
+use crate::evol_prim::*;
+
+#[derive(Debug, Clone)]
+pub struct Species {
+    pub id: u64,
+    // Organisms join the nearest species whose representative is within the threshold
+    pub representative: BaseSeq,
+    pub size: usize,
+    pub born: i32,
+    // Nearest existing species when this one was founded. Organisms don't record their
+    // parents, so this is the closest available proxy for the lineage a species split from
+    pub parent: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SpeciesEvent {
+    // parent is Some for a species that split off from an existing one
+    Born {
+        t: i32,
+        species: u64,
+        parent: Option<u64>,
+    },
+    Extinct {
+        t: i32,
+        species: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct SpeciesCensus {
+    pub t: i32,
+    // (species id, member count), largest species first
+    pub sizes: Vec<(u64, usize)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Speciation {
+    pub threshold: f32,
+    pub distance: fn(&[Base], &[Base]) -> f32,
+    // When set, children are admitted into a full population with weight inversely
+    // proportional to their species size (NEAT-style explicit fitness sharing)
+    pub fitness_sharing: bool,
+    pub species: Vec<Species>,
+    pub events: Vec<SpeciesEvent>,
+    pub history: Vec<SpeciesCensus>,
+    // Organism id -> species id
+    membership: HashMap<u64, u64>,
+    next_species_id: u64,
+}
+
+impl Speciation {
+    pub fn new(threshold: f32, distance: fn(&[Base], &[Base]) -> f32, fitness_sharing: bool) -> Self {
+        Speciation {
+            threshold,
+            distance,
+            fitness_sharing,
+            species: Vec::new(),
+            events: Vec::new(),
+            history: Vec::new(),
+            membership: HashMap::new(),
+            next_species_id: 0,
+        }
+    }
+
+    pub fn species_of(&self, org_id: u64) -> Option<u64> {
+        self.membership.get(&org_id).copied()
+    }
+
+    // Nearest species by distance to its representative, with that distance
+    fn nearest(&self, genes: &[Base]) -> Option<(u64, f32)> {
+        self.species
+            .iter()
+            .map(|s| (s.id, (self.distance)(&s.representative, genes)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // Species the organism belongs to or would join; None if it would found a new one
+    fn species_for<O>(&self, org: &Organism<O>) -> Option<u64> {
+        self.species_of(org.id).or_else(|| {
+            self.nearest(&org.genes)
+                .filter(|(_, d)| *d <= self.threshold)
+                .map(|(id, _)| id)
+        })
+    }
+
+    fn size_of(&self, species: u64) -> usize {
+        self.species
+            .iter()
+            .find(|s| s.id == species)
+            .map_or(0, |s| s.size)
+    }
+
+    /**
+     * Assign every organism to a species, founding new species for organisms not within
+     * the threshold of any existing representative. Organisms keep the species they were
+     * assigned in earlier steps. Species sizes are recounted over the given organisms.
+     */
+    pub fn assign<'o, O: 'o>(
+        &mut self,
+        organisms: impl Iterator<Item = &'o Organism<O>>,
+        t: i32,
+    ) {
+        let existing = self.species.len();
+        let mut assigned = Vec::new();
+        for org in organisms {
+            if let Some(species) = self.species_of(org.id) {
+                assigned.push(species);
+                continue;
+            }
+            let nearest = self.nearest(&org.genes);
+            let species = match nearest {
+                Some((id, d)) if d <= self.threshold => id,
+                _ => {
+                    let id = self.next_species_id;
+                    self.next_species_id += 1;
+                    // Only species that existed before this step can be split from
+                    let parent = nearest
+                        .map(|(id, _)| id)
+                        .filter(|p| self.species[..existing].iter().any(|s| s.id == *p));
+                    self.species.push(Species {
+                        id,
+                        representative: org.genes.clone(),
+                        size: 0,
+                        born: t,
+                        parent,
+                    });
+                    self.events.push(SpeciesEvent::Born {
+                        t,
+                        species: id,
+                        parent,
+                    });
+                    id
+                }
+            };
+            self.membership.insert(org.id, species);
+            assigned.push(species);
+        }
+        self.recount(assigned.into_iter());
+    }
+
+    fn recount(&mut self, members: impl Iterator<Item = u64>) {
+        let mut counts = HashMap::new();
+        for s in members {
+            *counts.entry(s).or_insert(0) += 1;
+        }
+        for s in &mut self.species {
+            s.size = *counts.get(&s.id).unwrap_or(&0);
+        }
+    }
+
+    /**
+     * Relative weight of an organism under fitness sharing, by the size of the species it
+     * belongs to or would join. Organisms not yet assigned are not assigned, so children
+     * that find no room never found a species.
+     */
+    pub fn shared_weight<O>(&self, org: &Organism<O>) -> f64 {
+        match self.species_for(org).map(|s| self.size_of(s)) {
+            Some(size) if size > 0 => 1.0 / size as f64,
+            _ => 1.0,
+        }
+    }
+
+    /**
+     * Record species sizes for the surviving population at the end of step t. Species
+     * without members go extinct, and each surviving species picks a random member as its
+     * representative for the next step.
+     */
+    pub fn census<O>(&mut self, organisms: &[Organism<O>], t: i32, rng: &mut ThreadRng) {
+        let membership = organisms
+            .iter()
+            .filter_map(|o| self.species_of(o.id).map(|s| (o.id, s)))
+            .collect::<HashMap<u64, u64>>();
+        self.recount(membership.values().copied());
+        self.membership = membership;
+
+        for s in self.species.iter().filter(|s| s.size == 0) {
+            self.events.push(SpeciesEvent::Extinct { t, species: s.id });
+        }
+        self.species.retain(|s| s.size > 0);
+
+        for s in &mut self.species {
+            let members = organisms
+                .iter()
+                .filter(|o| self.membership.get(&o.id) == Some(&s.id))
+                .collect::<Vec<_>>();
+            if let Some(rep) = members.choose(rng) {
+                s.representative = rep.genes.clone();
+            }
+        }
+
+        let mut sizes = self
+            .species
+            .iter()
+            .map(|s| (s.id, s.size))
+            .collect::<Vec<_>>();
+        sizes.sort_by_key(|s| std::cmp::Reverse(s.1));
+        self.history.push(SpeciesCensus { t, sizes });
+    }
+}
+
+impl fmt::Display for SpeciesCensus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "species {}, sizes [", self.sizes.len())?;
+        for (i, (id, size)) in self.sizes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}:{}", id, size)?;
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diversity::hamming;
+    use crate::evol_prim::Base::*;
+    use crate::sim::Simulation;
+
+    fn speciation() -> Speciation {
+        Speciation::new(1.0, |a, b| hamming(a, b) as f32, false)
+    }
+
+    fn born(speciation: &Speciation) -> Vec<(u64, Option<u64>)> {
+        speciation
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                SpeciesEvent::Born {
+                    species, parent, ..
+                } => Some((*species, *parent)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn species_are_born_split_and_go_extinct() {
+        let mut rng = rand::thread_rng();
+        let mut s = speciation();
+        let a = Organism::new(vec![A, A, A, A], (), 0);
+        let a2 = Organism::new(vec![A, A, A, C], (), 0);
+        let g = Organism::new(vec![G, G, G, G], (), 0);
+        let mut population = vec![a.clone(), a2.clone(), g.clone()];
+        s.assign(population.iter(), 0);
+        s.census(&population, 0, &mut rng);
+        assert_eq!(born(&s), vec![(0, None), (1, None)]);
+        assert_eq!(s.species_of(a2.id), Some(0));
+        assert_eq!(s.history[0].sizes, vec![(0, 2), (1, 1)]);
+
+        // A new genotype splits off the species nearest to it, and G dies out
+        let split = Organism::new(vec![A, A, T, T], (), 1);
+        population = vec![a, split.clone()];
+        s.assign(population.iter(), 1);
+        s.census(&population, 1, &mut rng);
+        assert_eq!(born(&s)[2], (2, Some(0)));
+        assert_eq!(s.species_of(split.id), Some(2));
+        assert_eq!(s.species_of(g.id), None);
+        assert!(matches!(
+            s.events.last(),
+            Some(SpeciesEvent::Extinct { t: 1, species: 1 })
+        ));
+        assert_eq!(s.history[1].sizes.len(), 2);
+    }
+
+    #[test]
+    fn children_without_room_found_no_species() {
+        let population = (0..2)
+            .map(|_| Organism::new(vec![A, A, A, A], (), 0))
+            .collect();
+        let mut sim = Simulation {
+            R: &|_: &Organism<()>, _: &(), _: &mut ThreadRng| vec![vec![G, G, G, G]],
+            D: &|_: &Organism<()>, _: &(), _: &mut ThreadRng| false,
+            B: &|_: &BaseSeq, _: &mut ThreadRng| (),
+            U: &|_: &mut Organism<()>, _: &(), _: &mut ThreadRng| {},
+            L: &|_: &mut Organism<()>, _: &(), _: &mut ThreadRng| {},
+            organisms: population,
+            environment: (),
+            max_sequences: 2,
+            t: 0,
+            max_t: 3,
+            rng: rand::thread_rng(),
+            speciation: Some(speciation()),
+            hgt: None,
+            tracker: None,
+            elitism: None,
+            hall_of_fame: None,
+            soup: None,
+        };
+        sim.run(None).unwrap();
+        let s = sim.speciation.unwrap();
+        assert_eq!(born(&s), vec![(0, None)]);
+        assert!(s
+            .events
+            .iter()
+            .all(|e| matches!(e, SpeciesEvent::Born { .. })));
+        assert!(s.history.iter().all(|c| c.sizes == vec![(0, 2)]));
+    }
+}
@@ -0,0 +1,170 @@
+// Frequency tracking of genome motifs/traits, with fixation and loss detection
+
+use std::fmt;
+
+use crate::evol_prim::*;
+
+#[derive(Clone, Copy)]
+pub enum Marker<'a> {
+    // e.g. E1_REPRODUCE_PREFIX
+    Prefix(&'static [Base]),
+    Contains(&'static [Base]),
+    // Any other genome property, e.g. |s| count_AT_repetitions(s) >= 3
+    Predicate(&'a dyn Fn(&BaseSeq) -> bool),
+}
+
+impl<'a> Marker<'a> {
+    pub fn matches(&self, seq: &BaseSeq) -> bool {
+        match self {
+            Marker::Prefix(p) => seq.starts_with(p),
+            Marker::Contains(m) => m.is_empty() || seq.windows(m.len()).any(|w| w == *m),
+            Marker::Predicate(f) => f(seq),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TrackedMarker<'a> {
+    pub name: String,
+    pub marker: Marker<'a>,
+    // (t, fraction of the population carrying the marker)
+    pub frequencies: Vec<(i32, f32)>,
+    pub first_seen: Option<i32>,
+    // First step the frequency reached the tracker's fixation threshold
+    pub fixed_at: Option<i32>,
+    // Step the marker disappeared after having been seen. Cleared if it reappears.
+    pub lost_at: Option<i32>,
+}
+
+impl<'a> TrackedMarker<'a> {
+    pub fn time_to_fixation(&self) -> Option<i32> {
+        Some(self.fixed_at? - self.first_seen?)
+    }
+
+    pub fn current_frequency(&self) -> f32 {
+        self.frequencies.last().map_or(0.0, |(_, f)| *f)
+    }
+}
+
+#[derive(Clone)]
+pub struct MarkerTracker<'a> {
+    // Frequency at which a marker counts as fixed. Mutation keeps most markers just below 1.0
+    // once they have swept, so something like 0.95 is usually more useful.
+    pub fixation_threshold: f32,
+    pub markers: Vec<TrackedMarker<'a>>,
+}
+
+impl<'a> MarkerTracker<'a> {
+    pub fn new(fixation_threshold: f32) -> Self {
+        MarkerTracker {
+            fixation_threshold,
+            markers: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &str, marker: Marker<'a>) {
+        self.markers.push(TrackedMarker {
+            name: name.to_string(),
+            marker,
+            frequencies: Vec::new(),
+            first_seen: None,
+            fixed_at: None,
+            lost_at: None,
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TrackedMarker<'a>> {
+        self.markers.iter().find(|m| m.name == name)
+    }
+
+    pub fn record<O>(&mut self, organisms: &[Organism<O>], t: i32) {
+        for m in &mut self.markers {
+            let carriers = organisms
+                .iter()
+                .filter(|o| m.marker.matches(&o.genes))
+                .count();
+            let freq = if organisms.is_empty() {
+                0.0
+            } else {
+                carriers as f32 / organisms.len() as f32
+            };
+            m.frequencies.push((t, freq));
+
+            if carriers > 0 {
+                m.first_seen.get_or_insert(t);
+                m.lost_at = None;
+            } else if m.first_seen.is_some() && m.lost_at.is_none() {
+                m.lost_at = Some(t);
+            }
+            if m.fixed_at.is_none() && freq >= self.fixation_threshold {
+                m.fixed_at = Some(t);
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Display for MarkerTracker<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, m) in self.markers.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let step = |s: Option<i32>| s.map_or("-".to_string(), |s| s.to_string());
+            write!(
+                f,
+                "{}: frequency {:.3}, first seen {}, fixed {}, lost {}, time to fixation {}",
+                m.name,
+                m.current_frequency(),
+                step(m.first_seen),
+                step(m.fixed_at),
+                step(m.lost_at),
+                step(m.time_to_fixation())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evol_prim::Base::*;
+
+    fn population(carriers: usize, size: usize) -> Vec<Organism<()>> {
+        (0..size)
+            .map(|i| {
+                let genes = if i < carriers {
+                    vec![A, T, A, T]
+                } else {
+                    vec![C; 4]
+                };
+                Organism::new(genes, (), 0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn records_first_sighting_fixation_and_loss() {
+        let mut tracker = MarkerTracker::new(0.9);
+        tracker.register("prefix", Marker::Prefix(&[A, T]));
+        tracker.register("c", Marker::Contains(&[C, C]));
+        for (t, carriers) in [0, 1, 5, 10, 10, 0, 10].iter().enumerate() {
+            tracker.record(&population(*carriers, 10), t as i32);
+        }
+
+        let prefix = tracker.get("prefix").unwrap();
+        assert_eq!(prefix.first_seen, Some(1));
+        assert_eq!(prefix.fixed_at, Some(3));
+        assert_eq!(prefix.time_to_fixation(), Some(2));
+        // Lost at step 5, but it came back at step 6
+        assert_eq!(prefix.lost_at, None);
+        assert_eq!(prefix.current_frequency(), 1.0);
+
+        let c = tracker.get("c").unwrap();
+        assert_eq!(c.first_seen, Some(0));
+        assert_eq!(c.fixed_at, Some(0));
+        // Lost at step 3, back at step 5 and lost again at step 6
+        assert_eq!(c.lost_at, Some(6));
+        assert_eq!(c.frequencies[2], (2, 0.5));
+    }
+}
@@ -0,0 +1,174 @@
+// Transposable elements: motifs that copy themselves to random genome locations when the
+// genome is cloned, whatever that does to the organism carrying them
+
+use rand::prelude::ThreadRng;
+use rand::Rng;
+
+use crate::evol_prim::*;
+
+#[derive(Debug, Clone)]
+pub struct Transposon {
+    pub motif: &'static [Base],
+    // Chance each copy in a genome inserts a further copy of itself per cloning
+    pub rate: f32,
+}
+
+impl Transposon {
+    // Start positions of non-overlapping copies of the motif
+    pub fn copies(&self, seq: &[Base]) -> Vec<usize> {
+        let mut positions = Vec::new();
+        let mut i = 0;
+        while !self.motif.is_empty() && i + self.motif.len() <= seq.len() {
+            if seq[i..].starts_with(self.motif) {
+                positions.push(i);
+                i += self.motif.len();
+            } else {
+                i += 1;
+            }
+        }
+        positions
+    }
+
+    pub fn count(&self, seq: &[Base]) -> usize {
+        self.copies(seq).len()
+    }
+
+    // Each copy present before transposition independently inserts one new copy
+    pub fn transpose(&self, seq: &[Base], rng: &mut ThreadRng) -> BaseSeq {
+        let mut new = seq.to_vec();
+        for _ in 0..self.count(seq) {
+            if rng.gen::<f32>() < self.rate {
+                let at = rng.gen_range(0..=new.len());
+                new.splice(at..at, self.motif.iter().copied());
+            }
+        }
+        new
+    }
+}
+
+// Transposition of every element, then clone_with_mutation
+pub fn clone_with_transposition(
+    seq: &BaseSeq,
+    rng: &mut ThreadRng,
+    transposons: &[Transposon],
+    insertion_prob: f32,
+    deletion_prob: f32,
+    base_change_prob: f32,
+) -> BaseSeq {
+    let mut transposed = seq.clone();
+    for t in transposons {
+        transposed = t.transpose(&transposed, rng);
+    }
+    clone_with_mutation(
+        &transposed,
+        rng,
+        insertion_prob,
+        deletion_prob,
+        base_change_prob,
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct TransposonLoad {
+    pub t: i32,
+    pub mean_copies: f32,
+    pub max_copies: usize,
+    // Fraction of organisms carrying at least one copy
+    pub carrier_fraction: f32,
+    // Fraction of all bases belonging to a copy
+    pub genome_fraction: f32,
+}
+
+impl TransposonLoad {
+    pub fn compute<O>(organisms: &[Organism<O>], transposon: &Transposon, t: i32) -> Self {
+        let counts = organisms
+            .iter()
+            .map(|o| transposon.count(&o.genes))
+            .collect::<Vec<usize>>();
+        let n = organisms.len().max(1) as f32;
+        let bases = organisms.iter().map(|o| o.genes.len()).sum::<usize>();
+        TransposonLoad {
+            t,
+            mean_copies: counts.iter().sum::<usize>() as f32 / n,
+            max_copies: counts.iter().copied().max().unwrap_or(0),
+            carrier_fraction: counts.iter().filter(|c| **c > 0).count() as f32 / n,
+            genome_fraction: if bases == 0 {
+                0.0
+            } else {
+                (counts.iter().sum::<usize>() * transposon.motif.len()) as f32 / bases as f32
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for TransposonLoad {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "t {}, transposon copies mean {:.2} max {}, carriers {:.3}, genome fraction {:.3}",
+            self.t, self.mean_copies, self.max_copies, self.carrier_fraction, self.genome_fraction
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evol_prim::Base::*;
+
+    fn element(rate: f32) -> Transposon {
+        Transposon {
+            motif: &[C, G, C],
+            rate,
+        }
+    }
+
+    #[test]
+    fn copies_do_not_overlap() {
+        let t = element(0.0);
+        // CGCGC holds the motif at 0 and 2, but only one copy fits
+        assert_eq!(t.copies(&[C, G, C, G, C, A, C, G, C]), vec![0, 6]);
+        assert_eq!(t.count(&[C, G]), 0);
+        let empty = Transposon {
+            motif: &[],
+            rate: 1.0,
+        };
+        assert_eq!(empty.count(&[A, C]), 0);
+    }
+
+    #[test]
+    fn every_copy_inserts_one_more() {
+        let mut rng = rand::thread_rng();
+        let seq = [vec![C, G, C], vec![A; 10], vec![C, G, C]].concat();
+        for _ in 0..20 {
+            let transposed = element(1.0).transpose(&seq, &mut rng);
+            assert_eq!(transposed.len(), seq.len() + 2 * 3);
+        }
+        assert_eq!(element(0.0).transpose(&seq, &mut rng), seq);
+    }
+
+    #[test]
+    fn load_of_a_population() {
+        let t = element(0.0);
+        let organisms = [
+            [vec![C, G, C], vec![A; 3]].concat(),
+            [vec![C, G, C], vec![C, G, C]].concat(),
+            vec![A; 6],
+            vec![T; 6],
+        ]
+        .into_iter()
+        .map(|s| Organism::new(s, (), 0))
+        .collect::<Vec<Organism<()>>>();
+        let load = TransposonLoad::compute(&organisms, &t, 7);
+        assert_eq!(load.t, 7);
+        assert_eq!(load.mean_copies, 0.75);
+        assert_eq!(load.max_copies, 2);
+        assert_eq!(load.carrier_fraction, 0.5);
+        // 3 copies of 3 bases in 24 bases
+        assert_eq!(load.genome_fraction, 0.375);
+        assert_eq!(
+            TransposonLoad::compute::<()>(&[], &t, 0).genome_fraction,
+            0.0
+        );
+    }
+}
@@ -0,0 +1,271 @@
+// Graphviz DOT export of brains and genome maps, e.g. `dot -Tpng brain.dot -o brain.png`
+//
+// brain_dot draws neurons colored by type (input blue, control grey, output red) and labeled
+// with their id and labeled_neurons label, and edges as thick as their weight is large,
+// black if excitatory and red if inhibitory. genome_dot draws which loci of a genome set which
+// phenotype fields; gene_loci and byte_loci list them for the two kinds of genome in use.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::brain::{Brain, NeuronId, NeuronType};
+use crate::evol_prim::Base;
+use crate::genes::GeneSchema;
+
+// Edge pen width per unit of |weight|, on top of a minimum of 0.5
+const PEN_WIDTH_PER_WEIGHT: f64 = 1.0;
+const MAX_PEN_WIDTH: f64 = 6.0;
+
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+pub fn brain_dot(brain: &Brain, name: &str) -> String {
+    let mut labels: HashMap<NeuronId, Vec<&str>> = HashMap::new();
+    for (label, id) in &brain.labeled_neurons {
+        labels.entry(*id).or_default().push(label);
+    }
+    let mut dot = String::new();
+    writeln!(dot, "digraph {} {{", quote(name)).unwrap();
+    writeln!(dot, "    rankdir=LR;").unwrap();
+    writeln!(dot, "    node [shape=circle, style=filled];").unwrap();
+    for (neuron_type, color, rank) in [
+        (NeuronType::Input, "lightblue", "source"),
+        (NeuronType::Control, "lightgrey", "same"),
+        (NeuronType::Output, "salmon", "sink"),
+    ] {
+        writeln!(dot, "    subgraph {{").unwrap();
+        writeln!(dot, "        rank={};", rank).unwrap();
+        for id in brain.ids(neuron_type) {
+            let mut text = id.to_string();
+            if let Some(names) = labels.get_mut(id) {
+                names.sort();
+                text = format!("{}\n{}", text, names.join("\n"));
+            }
+            writeln!(
+                dot,
+                "        n{} [label={}, fillcolor={}];",
+                id,
+                quote(&text),
+                color
+            )
+            .unwrap();
+        }
+        writeln!(dot, "    }}").unwrap();
+    }
+    for e in &brain.edges {
+        let width = (0.5 + e.weight.abs() * PEN_WIDTH_PER_WEIGHT).min(MAX_PEN_WIDTH);
+        let color = if e.weight < 0.0 { "red" } else { "black" };
+        writeln!(
+            dot,
+            "    n{} -> n{} [penwidth={:.2}, color={}, label=\"{:.2}\"];",
+            e.source, e.target, width, color, e.weight
+        )
+        .unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// Bases of a genome that set a phenotype field
+#[derive(Debug, Clone)]
+pub struct LocusField {
+    pub field: String,
+    pub loci: Range<usize>,
+    // Whether the loci are expressed, e.g. false for a broken gene copy
+    pub functional: bool,
+}
+
+/**
+ * Each gene copy in seq, named by kind_names[kind]. The loci are the whole gene, start to
+ * stop codon.
+ */
+pub fn gene_loci(schema: &GeneSchema, seq: &[Base], kind_names: &[&str]) -> Vec<LocusField> {
+    schema
+        .decode(seq)
+        .into_iter()
+        .map(|g| {
+            let end = g.locus + schema.start.len() + 1 + g.payload.len();
+            LocusField {
+                field: kind_names.get(g.kind).unwrap_or(&"?").to_string(),
+                loci: g.locus..(end + schema.stop.len()).min(seq.len()),
+                functional: g.functional,
+            }
+        })
+        .collect()
+}
+
+/**
+ * Fields read as consecutive 4-base bytes, like e10::build and e13::build read them; fields
+ * past the end of a genome of seq_len bases are left out.
+ */
+pub fn byte_loci(field_names: &[&str], seq_len: usize) -> Vec<LocusField> {
+    field_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| LocusField {
+            field: name.to_string(),
+            loci: i * 4..((i + 1) * 4).min(seq_len),
+            functional: (i + 1) * 4 <= seq_len,
+        })
+        .filter(|f| !f.loci.is_empty())
+        .collect()
+}
+
+/**
+ * Genome loci on the left, each linked to the phenotype field it sets on the right. Loci
+ * setting no field are shown as gaps; non-functional loci are dashed.
+ */
+pub fn genome_dot(seq_len: usize, fields: &[LocusField], name: &str) -> String {
+    let mut dot = String::new();
+    writeln!(dot, "digraph {} {{", quote(name)).unwrap();
+    writeln!(dot, "    rankdir=LR;").unwrap();
+    writeln!(dot, "    node [shape=box];").unwrap();
+
+    let mut sorted = fields.iter().enumerate().collect::<Vec<_>>();
+    sorted.sort_by_key(|(_, f)| (f.loci.start, f.loci.end));
+    let mut segments = Vec::new();
+    let mut covered = 0;
+    for (i, f) in &sorted {
+        if f.loci.start > covered {
+            segments.push((format!("gap{}", covered), covered..f.loci.start, None));
+        }
+        segments.push((format!("locus{}", i), f.loci.clone(), Some(*f)));
+        covered = covered.max(f.loci.end);
+    }
+    if covered < seq_len {
+        segments.push((format!("gap{}", covered), covered..seq_len, None));
+    }
+
+    writeln!(dot, "    subgraph cluster_genome {{").unwrap();
+    writeln!(dot, "        label=\"genome ({} bases)\";", seq_len).unwrap();
+    for (id, loci, field) in &segments {
+        let style = match field {
+            Some(f) if f.functional => "solid",
+            Some(_) => "dashed",
+            None => "dotted",
+        };
+        writeln!(
+            dot,
+            "        {} [label=\"{}..{}\", style={}];",
+            id, loci.start, loci.end, style
+        )
+        .unwrap();
+    }
+    // Keep the loci in genome order
+    let ids = segments
+        .iter()
+        .map(|(id, _, _)| id.as_str())
+        .collect::<Vec<&str>>();
+    if ids.len() > 1 {
+        writeln!(dot, "        {} [style=invis];", ids.join(" -> ")).unwrap();
+    }
+    writeln!(dot, "    }}").unwrap();
+
+    let mut names = fields
+        .iter()
+        .map(|f| f.field.as_str())
+        .collect::<Vec<&str>>();
+    names.sort();
+    names.dedup();
+    writeln!(dot, "    subgraph cluster_phenotype {{").unwrap();
+    writeln!(dot, "        label=\"phenotype\";").unwrap();
+    for name in &names {
+        writeln!(
+            dot,
+            "        {} [shape=ellipse];",
+            quote(&format!("field {}", name))
+        )
+        .unwrap();
+    }
+    writeln!(dot, "    }}").unwrap();
+
+    for (id, _, field) in &segments {
+        if let Some(f) = field {
+            let style = if f.functional { "solid" } else { "dashed" };
+            writeln!(
+                dot,
+                "    {} -> {} [style={}];",
+                id,
+                quote(&format!("field {}", f.field)),
+                style
+            )
+            .unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+pub fn save(dot: &str, path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, dot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::{Edge, Neuron};
+
+    #[test]
+    fn small_brain() {
+        let mut brain = Brain::new();
+        brain.add_neuron(Neuron::new(0, 0.0, 0.0), NeuronType::Input, Some("in"));
+        brain.add_neuron(Neuron::new(1, 0.0, 0.0), NeuronType::Control, None);
+        brain.add_neuron(Neuron::new(2, 0.0, 0.0), NeuronType::Output, Some("out"));
+        brain.add_edge(Edge::new(0, 1, 1.5));
+        brain.add_edge(Edge::new(1, 2, -10.0));
+        let expected = r#"digraph "a \"brain\"" {
+    rankdir=LR;
+    node [shape=circle, style=filled];
+    subgraph {
+        rank=source;
+        n0 [label="0\nin", fillcolor=lightblue];
+    }
+    subgraph {
+        rank=same;
+        n1 [label="1", fillcolor=lightgrey];
+    }
+    subgraph {
+        rank=sink;
+        n2 [label="2\nout", fillcolor=salmon];
+    }
+    n0 -> n1 [penwidth=2.00, color=black, label="1.50"];
+    n1 -> n2 [penwidth=6.00, color=red, label="-10.00"];
+}
+"#;
+        assert_eq!(brain_dot(&brain, "a \"brain\""), expected);
+    }
+
+    #[test]
+    fn genome_map_shows_gaps_and_broken_loci() {
+        let mut fields = byte_loci(&["speed", "size", "color", "shape"], 10);
+        // color has only 2 of its 4 bases and shape none
+        assert_eq!(fields.len(), 3);
+        assert_eq!(
+            (fields[2].loci.clone(), fields[2].functional),
+            (8..10, false)
+        );
+        fields.push(LocusField {
+            field: "speed".to_string(),
+            loci: 12..14,
+            functional: false,
+        });
+        let dot = genome_dot(16, &fields, "g");
+        assert!(dot.contains("locus0 [label=\"0..4\", style=solid];"));
+        assert!(dot.contains("locus2 [label=\"8..10\", style=dashed];"));
+        assert!(dot.contains("gap10 [label=\"10..12\", style=dotted];"));
+        assert!(
+            dot.contains("locus0 -> locus1 -> locus2 -> gap10 -> locus3 -> gap14 [style=invis];")
+        );
+        assert!(dot.contains("locus3 -> \"field speed\" [style=dashed];"));
+        assert_eq!(dot.matches("[shape=ellipse]").count(), 3);
+    }
+}
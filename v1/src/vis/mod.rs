@@ -1,6 +1,8 @@
 extern crate image;
 extern crate rand;
 
+pub mod dot;
+
 use std::cmp::min;
 
 use rand::Rng;